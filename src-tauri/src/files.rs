@@ -1,7 +1,7 @@
 use crate::paths::{PathBufExt, PathExt};
 use crate::storage::do_log;
 use crate::tree::node::from_node;
-use crate::tree::Tree;
+use crate::tree::{options::WalkOptions, Tree};
 use chrono::offset::Local;
 use chrono::DateTime;
 use notify::{
@@ -408,13 +408,13 @@ pub async fn read_directory(dir: &str) -> Result<FolderData, String> {
 
 // Read files and its information of a directory resursively
 pub fn read_dir(dir: &str) -> Result<Tree, String> {
-  Tree::init(dir, None, true)
+  Tree::init(dir, None, true, &WalkOptions::default()).map_err(|e| e.to_string())
 }
 
 // Get array of files of a directory
 #[tauri::command]
 pub async fn list_directory(dir: &str) -> Result<Vec<FileMetaData>, String> {
-  let tree = Tree::init(dir, Some(1), false);
+  let tree = Tree::init(dir, Some(1), false, &WalkOptions::default());
   // println!(">> dir tree: {:?}", tree);
   let nodes = tree.map(|t| t.children_vec()).unwrap_or_default();
   let metas: Vec<FileMetaData> = nodes.iter().filter_map(|n| from_node(n)).collect();
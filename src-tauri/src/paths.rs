@@ -1,7 +1,10 @@
 // code modified from  https://github.com/rhysd/path-slash
 // MIT License Copyright (c) 2018 rhysd
 
-use std::path::{Path, PathBuf, MAIN_SEPARATOR};
+use std::{
+  borrow::Cow,
+  path::{Path, PathBuf, MAIN_SEPARATOR},
+};
 #[cfg(windows)]
 use std::path::{Component, Prefix};
 
@@ -68,6 +71,61 @@ impl PathExt for Path {
   }
 }
 
+// Drop the `\\?\` or `\\?\UNC\` prefix `fs::canonicalize` puts in front of
+// paths on Windows, if the path means the same without it.
+//
+// Verbatim paths take `.` and `..` literally and are the only way to reach
+// paths longer than `MAX_PATH`, those are kept as they are.
+//
+#[cfg(target_os = "windows")]
+pub fn strip_verbatim(path: PathBuf) -> PathBuf {
+  let is_unc = match path.components().next() {
+    Some(Component::Prefix(p)) => matches!(p.kind(), Prefix::VerbatimUNC(..)),
+    _ => return path,
+  };
+  let is_plain = if is_unc {
+    has_plain_components(path.components().skip(1))
+  } else {
+    is_safe_to_strip_prefix(&path)
+  };
+
+  let stripped = match path.to_str() {
+    Some(s) if is_plain && s.len() < 260 => {
+      if is_unc {
+        s.strip_prefix(r"\\?\UNC\").map(|rest| format!(r"\\{rest}"))
+      } else {
+        s.strip_prefix(r"\\?\").map(str::to_string)
+      }
+    }
+    _ => None,
+  };
+  stripped.map(PathBuf::from).unwrap_or(path)
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn strip_verbatim(path: PathBuf) -> PathBuf {
+  path
+}
+
+// Form of `path` to compare it by: lowercased where the filesystem is
+// case-insensitive by default, on macOS and Windows, so `Foo.md` and
+// `foo.md` are the same. Non UTF-8 paths are compared as they are.
+//
+#[cfg(any(target_os = "macos", target_os = "windows"))]
+pub fn case_key(path: &Path) -> Cow<'_, Path> {
+  match path.to_str() {
+    Some(s) if s.chars().any(char::is_uppercase) => {
+      Cow::Owned(PathBuf::from(s.to_lowercase()))
+    }
+    _ => Cow::Borrowed(path),
+  }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+pub fn case_key(path: &Path) -> Cow<'_, Path> {
+  Cow::Borrowed(path)
+}
+
 #[cfg(windows)]
 fn is_safe_to_strip_prefix(path: &Path) -> bool {
   let mut components = path.components();
@@ -79,14 +137,15 @@ fn is_safe_to_strip_prefix(path: &Path) -> bool {
     _ => return false, // relative or empty
   }
 
-  for component in components {
-    match component {
-      Component::RootDir | Component::Normal(_) => {},
-      _ => return false, // UNC paths take things like ".." literally
-    };
-  }
+  has_plain_components(components)
+}
 
-  true
+// UNC paths take things like ".." literally
+#[cfg(windows)]
+fn has_plain_components<'a>(
+  mut components: impl Iterator<Item = Component<'a>>,
+) -> bool {
+  components.all(|c| matches!(c, Component::RootDir | Component::Normal(_)))
 }
 
 pub trait PathBufExt {
@@ -3,6 +3,19 @@ mod tests {
   use crate::files::*;
   use crate::paths::{PathBufExt, PathExt};
   use crate::storage::*;
+  use crate::tree::{
+    canonicalize,
+    diff::{Renamed, TreeDiff},
+    kind::FileKind,
+    links::GraphEdge,
+    node::{Node, SerializableNode},
+    options::{Encoding, ReadMode, ReadPredicate, WalkOptions},
+    sort::{SortKey, SortOptions},
+    stats::{TreeStats, NO_EXTENSION, NO_LANGUAGE},
+    watch::{ChangeKind, Debouncer, TreeChange, TreeEvent},
+    Tree, TreeError,
+  };
+  use std::fs;
   use std::path::{Path, PathBuf};
 
   #[test]
@@ -364,4 +377,3189 @@ mod tests {
     assert_eq!(serde_json::Value::Null, store_data_0.data);
     assert_eq!(false, store_data_0.status);
   }
+
+  // Create a clean fixture dir for tree tests, entries ending with `/` are dirs
+  fn tree_fixture(name: &str, entries: &[(&str, &str)]) -> PathBuf {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"))
+      .join("../temp_tree")
+      .join(name);
+    fs::remove_dir_all(&root).unwrap_or(());
+    fs::create_dir_all(&root).unwrap();
+    for (rel, text) in entries {
+      let path = root.join(rel);
+      if rel.ends_with('/') {
+        fs::create_dir_all(&path).unwrap();
+      } else {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, text).unwrap();
+      }
+    }
+    root
+  }
+
+  // Same as `tree_fixture` with `count` files, `entry` gives the path and
+  // text of each
+  fn generated_fixture(
+    name: &str,
+    count: usize,
+    entry: impl Fn(usize) -> (String, String),
+  ) -> PathBuf {
+    let owned: Vec<(String, String)> = (0..count).map(entry).collect();
+    let entries: Vec<(&str, &str)> = owned
+      .iter()
+      .map(|(rel, text)| (rel.as_str(), text.as_str()))
+      .collect();
+    tree_fixture(name, &entries)
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn test_tree_follow_links_cycle() {
+    let root = tree_fixture("follow_links", &[("a/note.md", "# note")]);
+    std::os::unix::fs::symlink(&root, root.join("a/loop")).unwrap();
+
+    let opts = WalkOptions {
+      follow_links: true,
+      ..WalkOptions::default()
+    };
+    let tree = Tree::init(root.to_str().unwrap(), None, false, &opts).unwrap();
+    let names: Vec<String> = tree
+      .children_vec()
+      .iter()
+      .map(|n| n.file_name_lossy().to_string())
+      .collect();
+    assert_eq!(names, vec!["a"]);
+  }
+
+  #[test]
+  fn test_tree_git_ignore() {
+    let root = tree_fixture(
+      "git_ignore",
+      &[
+        (".gitignore", "target/"),
+        ("target/debug.log", ""),
+        ("notes/a.md", "# a"),
+      ],
+    );
+    let dir = root.to_str().unwrap();
+    let names = |opts: &WalkOptions| -> Vec<String> {
+      let mut names: Vec<String> = Tree::init(dir, None, false, opts)
+        .unwrap()
+        .children_vec()
+        .iter()
+        .map(|n| n.file_name_lossy().to_string())
+        .collect();
+      names.sort();
+      names
+    };
+
+    assert_eq!(names(&WalkOptions::default()), vec!["notes", "target"]);
+    let opts = WalkOptions {
+      git_ignore: true,
+      ..WalkOptions::default()
+    };
+    assert_eq!(names(&opts), vec!["notes"]);
+  }
+
+  #[test]
+  fn test_tree_show_hidden() {
+    let root = tree_fixture(
+      ".hidden_root",
+      &[(".hidden.md", "hidden"), ("visible.md", "visible")],
+    );
+    let dir = root.to_str().unwrap();
+    let names = |opts: &WalkOptions| -> Vec<String> {
+      let mut names: Vec<String> = Tree::init(dir, None, false, opts)
+        .unwrap()
+        .children_vec()
+        .iter()
+        .map(|n| n.file_name_lossy().to_string())
+        .collect();
+      names.sort();
+      names
+    };
+
+    // hidden root still traversed
+    assert_eq!(names(&WalkOptions::default()), vec!["visible.md"]);
+    let opts = WalkOptions {
+      show_hidden: true,
+      ..WalkOptions::default()
+    };
+    assert_eq!(names(&opts), vec![".hidden.md", "visible.md"]);
+  }
+
+  #[test]
+  fn test_tree_include_globs() {
+    let root = tree_fixture(
+      "include_globs",
+      &[
+        ("README.md", "# readme"),
+        ("image.png", ""),
+        ("assets/", ""),
+      ],
+    );
+    let opts = WalkOptions {
+      include_globs: vec![String::from("*.md")],
+      ..WalkOptions::default()
+    };
+    let tree = Tree::init(root.to_str().unwrap(), None, false, &opts).unwrap();
+    let mut names: Vec<String> = tree
+      .children_vec()
+      .iter()
+      .map(|n| n.file_name_lossy().to_string())
+      .collect();
+    names.sort();
+    // directories are kept even without any matching file
+    assert_eq!(names, vec!["README.md", "assets"]);
+  }
+
+  #[test]
+  fn test_tree_init_markdown() {
+    let root = tree_fixture(
+      "init_markdown",
+      &[
+        ("note.md", "# note"),
+        ("photo.png", ""),
+        ("sub/deep.mdx", ""),
+      ],
+    );
+    let tree = Tree::init_markdown(root.to_str().unwrap(), None, true).unwrap();
+    let mut names: Vec<String> = tree
+      .children_vec()
+      .iter()
+      .map(|n| n.file_name_lossy().to_string())
+      .collect();
+    names.sort();
+    assert_eq!(names, vec!["note.md", "sub"]);
+  }
+
+  #[test]
+  fn test_tree_dir_size() {
+    let root = tree_fixture(
+      "dir_size",
+      &[("a/x.md", "abc"), ("a/b/y.md", "hello"), ("z.md", "z")],
+    );
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(root.join("a/x.md"), root.join("link.md")).unwrap();
+
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+    let inner = tree.inner();
+    let dir_a = tree
+      .root
+      .children(inner)
+      .find(|id| inner[*id].get().file_name() == "a")
+      .unwrap();
+    assert_eq!(inner[dir_a].get().size, 0);
+    assert_eq!(tree.dir_size(dir_a), 8);
+
+    // a symlink reports its own size, not the target's
+    #[cfg(unix)]
+    {
+      let link = tree
+        .children_vec()
+        .into_iter()
+        .find(|n| n.file_name() == "link.md")
+        .unwrap();
+      let link_meta = fs::symlink_metadata(root.join("link.md")).unwrap();
+      assert_eq!(link.size, link_meta.len());
+    }
+  }
+
+  #[test]
+  fn test_tree_modified_time() {
+    let root = tree_fixture("modified_time", &[("note.md", "# note")]);
+    let dir = root.to_str().unwrap();
+    let modified = || {
+      Tree::init(dir, None, false, &WalkOptions::default())
+        .unwrap()
+        .children_vec()
+        .into_iter()
+        .find(|n| n.file_name() == "note.md")
+        .and_then(|n| n.modified())
+        .unwrap()
+    };
+
+    let before = modified();
+    fs::File::options()
+      .write(true)
+      .open(root.join("note.md"))
+      .unwrap()
+      .set_modified(before + std::time::Duration::from_secs(10))
+      .unwrap();
+    assert!(modified() > before);
+  }
+
+  #[test]
+  fn test_tree_to_serializable() {
+    let root = tree_fixture("serializable", &[("a.md", "a"), ("sub/b.md", "b")]);
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+
+    fn sort_children(node: &mut SerializableNode) {
+      node.children.sort_by(|a, b| a.name.cmp(&b.name));
+      node.children.iter_mut().for_each(sort_children);
+    }
+    let mut serialized = tree.to_serializable();
+    sort_children(&mut serialized);
+
+    let json = serde_json::to_string(&serialized).unwrap();
+    let deserialized: SerializableNode = serde_json::from_str(&json).unwrap();
+    assert_eq!(deserialized, serialized);
+
+    let canonical = canonicalize(&root).unwrap();
+    let leaf = |name: &str, path: PathBuf| SerializableNode {
+      name: name.to_string(),
+      path,
+      is_dir: false,
+      children: vec![],
+    };
+    let expected = SerializableNode {
+      name: String::from("serializable"),
+      path: canonical.clone(),
+      is_dir: true,
+      children: vec![
+        leaf("a.md", canonical.join("a.md")),
+        SerializableNode {
+          name: String::from("sub"),
+          path: canonical.join("sub"),
+          is_dir: true,
+          children: vec![leaf("b.md", canonical.join("sub/b.md"))],
+        },
+      ],
+    };
+    assert_eq!(serialized, expected);
+  }
+
+  #[test]
+  fn test_tree_to_json() {
+    let root = tree_fixture("to_json", &[("a.md", "a"), ("sub/b.md", "bb")]);
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+
+    let json = tree.to_json().unwrap();
+    // stable output for the same tree
+    assert_eq!(json, tree.to_json().unwrap());
+
+    let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(value["file_name"], "to_json");
+    let sub = value["children"]
+      .as_array()
+      .unwrap()
+      .iter()
+      .find(|c| c["file_name"] == "sub")
+      .unwrap();
+    assert_eq!(sub["is_dir"], true);
+    assert_eq!(sub["children"][0]["file_name"], "b.md");
+    assert_eq!(sub["children"][0]["size"], 2);
+    assert!(sub["children"][0]["modified"].is_object());
+  }
+
+  #[test]
+  fn test_tree_watch() {
+    let root = tree_fixture("watch", &[("a.md", "a")]);
+    let (tx, rx) = std::sync::mpsc::channel();
+    let handle = Tree::watch(
+      root.to_str().unwrap(),
+      true,
+      &WalkOptions::default(),
+      move |e| {
+        tx.send(e).unwrap_or(());
+      },
+    )
+    .unwrap();
+
+    fs::write(root.join("b.md"), "b").unwrap();
+    let event = rx.recv_timeout(std::time::Duration::from_secs(5)).unwrap();
+    match event {
+      TreeEvent::Created(node) => {
+        assert_eq!(node.file_name(), "b.md");
+        // read like `init` with the same mode would
+        assert_eq!(node.file_text.as_deref(), Some("b"));
+      }
+      other => panic!("unexpected event: {:?}", other),
+    }
+    drop(handle);
+  }
+
+  #[test]
+  fn test_tree_insert_path() {
+    let root = tree_fixture("insert_path", &[("a.md", "a"), ("sub/b.md", "b")]);
+    let mut tree =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+
+    fs::write(root.join("sub/new.md"), "new").unwrap();
+    let new_id = tree.insert_path(&root.join("sub/new.md")).unwrap();
+
+    let inner = tree.inner();
+    let sub = tree
+      .root
+      .children(inner)
+      .find(|id| inner[*id].get().file_name() == "sub")
+      .unwrap();
+    assert!(sub.children(inner).any(|id| id == new_id));
+    assert_eq!(inner[new_id].get().depth, 2);
+
+    // parent not in the tree
+    fs::create_dir_all(root.join("other")).unwrap();
+    fs::write(root.join("other/c.md"), "c").unwrap();
+    assert!(tree.insert_path(&root.join("other/c.md")).is_err());
+  }
+
+  #[test]
+  fn test_tree_remove_path() {
+    let root = tree_fixture(
+      "remove_path",
+      &[("a.md", "a"), ("sub/b.md", "b"), ("sub/deep/c.md", "c")],
+    );
+    let mut tree =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+
+    fs::remove_dir_all(root.join("sub")).unwrap();
+    tree.remove_path(&root.join("sub")).unwrap();
+
+    let names: Vec<String> = tree
+      .children_vec()
+      .iter()
+      .map(|n| n.file_name_lossy().to_string())
+      .collect();
+    assert_eq!(names, vec!["a.md"]);
+    let inner = tree.inner();
+    let reachable = tree.root.descendants(inner).count();
+    assert_eq!(reachable, 2);
+    assert!(tree.remove_path(&root.join("sub/b.md")).is_err());
+  }
+
+  #[test]
+  fn test_tree_find_by_path() {
+    let root =
+      tree_fixture("find_by_path", &[("a.md", "a"), ("sub/deep/c.md", "c")]);
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+
+    let id = tree.find_by_path(&root.join("sub/./deep/c.md")).unwrap();
+    assert_eq!(tree.inner()[id].get().file_name(), "c.md");
+    assert_eq!(tree.find_by_path(&root), Some(tree.root));
+    assert_eq!(tree.find_by_path(&root.join("sub/none.md")), None);
+  }
+
+  #[test]
+  fn test_tree_iter() {
+    let root = tree_fixture(
+      "iter",
+      &[("a.md", "a"), ("sub/b.md", "b"), ("sub/deep/c.md", "c")],
+    );
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+
+    assert_eq!(tree.iter().filter(|n| !n.is_dir()).count(), 3);
+    assert_eq!(tree.iter().filter(|n| n.is_dir()).count(), 3);
+    // pre-order: every directory precedes its children
+    let paths: Vec<&Path> = tree.iter().map(|n| n.path()).collect();
+    assert_eq!(paths[0], tree.inner()[tree.root].get().path());
+    for (idx, path) in paths.iter().enumerate() {
+      if let Some(parent) = path.parent() {
+        if let Some(parent_idx) = paths.iter().position(|p| p == &parent) {
+          assert!(parent_idx < idx);
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn test_tree_iter_bfs() {
+    let root = tree_fixture("iter_bfs", &[("a/b/c.md", "c"), ("z.md", "z")]);
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+
+    let names: Vec<String> = tree
+      .iter_bfs()
+      .map(|n| n.file_name_lossy().to_string())
+      .collect();
+    assert_eq!(names.len(), 5);
+    assert_eq!(names[0], "iter_bfs");
+    let mut level_one = names[1..3].to_vec();
+    level_one.sort();
+    assert_eq!(level_one, vec!["a", "z.md"]);
+    assert_eq!(names[3..], [String::from("b"), String::from("c.md")]);
+  }
+
+  #[test]
+  fn test_tree_stats() {
+    let root = tree_fixture(
+      "stats",
+      &[("a.md", "abc"), ("sub/b.md", "hello"), ("sub/empty/", "")],
+    );
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+
+    let expected = TreeStats {
+      files: 2,
+      dirs: 3,
+      total_bytes: 8,
+    };
+    assert_eq!(tree.stats(), expected);
+  }
+
+  #[test]
+  fn test_tree_compute_dir_sizes() {
+    let root = tree_fixture(
+      "dir_sizes",
+      &[("a/x.md", "abc"), ("a/b/y.md", "hello"), ("c.md", "c")],
+    );
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(root.join("a/x.md"), root.join("link.md")).unwrap();
+
+    let opts = WalkOptions {
+      follow_links: true,
+      ..WalkOptions::default()
+    };
+    let mut tree = Tree::init(root.to_str().unwrap(), None, false, &opts).unwrap();
+    tree.compute_dir_sizes();
+
+    let size_of = |rel: &str| {
+      let id = tree.find_by_path(&root.join(rel)).unwrap();
+      tree.inner()[id].get().recursive_size
+    };
+    // the link into the tree is not counted twice
+    assert_eq!(tree.inner()[tree.root].get().recursive_size, 9);
+    assert_eq!(size_of("a"), 8);
+    assert_eq!(size_of("a/b"), 5);
+  }
+
+  #[test]
+  fn test_tree_sort_children() {
+    let root = tree_fixture(
+      "sort_children",
+      &[("b.md", "bbb"), ("A.md", "a"), ("c.md", "cc")],
+    );
+    let mut tree =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+    let names = |tree: &Tree| -> Vec<String> {
+      tree
+        .children_vec()
+        .iter()
+        .map(|n| n.file_name_lossy().to_string())
+        .collect()
+    };
+
+    tree.sort_children(SortKey::Name, true).unwrap();
+    assert_eq!(names(&tree), vec!["A.md", "b.md", "c.md"]);
+    tree.sort_children(SortKey::Size, true).unwrap();
+    assert_eq!(names(&tree), vec!["A.md", "c.md", "b.md"]);
+    tree.sort_children(SortKey::Size, false).unwrap();
+    assert_eq!(names(&tree), vec!["b.md", "c.md", "A.md"]);
+  }
+
+  #[test]
+  fn test_tree_sort_dirs_first() {
+    let root = tree_fixture(
+      "sort_dirs_first",
+      &[("a.md", ""), ("b/", ""), ("c.md", ""), ("d/", "")],
+    );
+    let mut tree =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+    let names = |tree: &Tree| -> Vec<String> {
+      tree
+        .children_vec()
+        .iter()
+        .map(|n| n.file_name_lossy().to_string())
+        .collect()
+    };
+
+    let opts = SortOptions {
+      dirs_first: true,
+      ..SortOptions::default()
+    };
+    tree.sort_children_with(SortKey::Name, &opts).unwrap();
+    assert_eq!(names(&tree), vec!["b", "d", "a.md", "c.md"]);
+    let opts = SortOptions {
+      ascending: false,
+      ..opts
+    };
+    tree.sort_children_with(SortKey::Name, &opts).unwrap();
+    assert_eq!(names(&tree), vec!["d", "b", "c.md", "a.md"]);
+  }
+
+  #[test]
+  fn test_tree_frontmatter() {
+    let root = tree_fixture(
+      "frontmatter",
+      &[
+        ("with.md", "---\ntitle: Hello\ntags: [a, b]\n---\n# Body"),
+        ("without.md", "# Just a note"),
+        ("broken.md", "---\ntitle: [unclosed\n---\nbody"),
+      ],
+    );
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, true, &WalkOptions::default())
+        .unwrap();
+    let frontmatter = |name: &str| {
+      tree
+        .children_vec()
+        .into_iter()
+        .find(|n| n.file_name() == name)
+        .unwrap()
+        .frontmatter
+    };
+
+    let with = frontmatter("with.md").unwrap();
+    assert_eq!(with["title"].as_str(), Some("Hello"));
+    assert_eq!(with["tags"].as_array().map(|s| s.len()), Some(2));
+    assert!(frontmatter("without.md").is_none());
+    assert!(frontmatter("broken.md").is_none());
+  }
+
+  #[test]
+  fn test_node_title() {
+    let root = tree_fixture(
+      "title",
+      &[
+        ("fenced.md", "```md\n# not a title\n```\n\n# Real Title\n"),
+        ("front.md", "---\ntitle: From Front\n---\n# Heading\n"),
+        ("plain.md", "no heading here"),
+      ],
+    );
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, true, &WalkOptions::default())
+        .unwrap();
+    let title = |name: &str| {
+      tree
+        .children_vec()
+        .into_iter()
+        .find(|n| n.file_name() == name)
+        .unwrap()
+        .title()
+    };
+
+    assert_eq!(title("fenced.md"), "Real Title");
+    assert_eq!(title("front.md"), "From Front");
+    assert_eq!(title("plain.md"), "plain");
+  }
+
+  #[test]
+  fn test_node_word_count() {
+    let text = "---\ntitle: Skip Me\n---\n# Title\n\n\
+      Hello **world**, this is [a link](https://mdsilo.com/a/b) and `code`.\n\
+      ```rust\nlet x = 1;\nlet y = 2;\n```\n";
+    let root = tree_fixture("word_count", &[("note.md", text)]);
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, true, &WalkOptions::default())
+        .unwrap();
+
+    let note = tree.children_vec().pop().unwrap();
+    // Title Hello world this is a link and code
+    assert_eq!(note.word_count, 9);
+  }
+
+  #[test]
+  fn test_node_tags() {
+    let text = "# Heading\n## Sub\n\
+      Plan #project and #todo, again #project.\n\
+      See https://mdsilo.com/page#fragment and `#code`.\n\
+      ```\n#fenced\n```\n";
+    let root = tree_fixture("node_tags", &[("note.md", text)]);
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, true, &WalkOptions::default())
+        .unwrap();
+
+    let note = tree.children_vec().pop().unwrap();
+    assert_eq!(note.tags, vec!["project", "todo"]);
+  }
+
+  #[test]
+  fn test_tag_index() {
+    let root = tree_fixture(
+      "tag_index",
+      &[("one.md", "first #a #b\n"), ("two.md", "second #a\n")],
+    );
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, true, &WalkOptions::default())
+        .unwrap();
+
+    let root = canonicalize(&root).unwrap();
+    let index = tree.tag_index();
+    assert_eq!(index.len(), 2);
+    let mut tagged_a = index["a"].clone();
+    tagged_a.sort();
+    assert_eq!(tagged_a, vec![root.join("one.md"), root.join("two.md")]);
+    assert_eq!(index["b"], vec![root.join("one.md")]);
+  }
+
+  #[test]
+  fn test_node_wikilinks() {
+    let text = "Plain [[Alpha]], aliased [[Beta|the beta]] and \
+      anchored [[Gamma#Section]], [[Delta#^block1]].\n\
+      Again [[Alpha]], self [[#Local]], code `[[Skipped]]`.\n\
+      ```\n[[Fenced]]\n```\n";
+    let root = tree_fixture("node_wikilinks", &[("note.md", text)]);
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, true, &WalkOptions::default())
+        .unwrap();
+
+    let note = tree.children_vec().pop().unwrap();
+    assert_eq!(note.links, vec!["Alpha", "Beta", "Gamma", "Delta"]);
+  }
+
+  #[test]
+  fn test_backlinks() {
+    let root = tree_fixture(
+      "backlinks",
+      &[
+        ("a.md", "to [[B]] and [[missing]]\n"),
+        ("sub/b.md", "back to [[a|the a]]\n"),
+      ],
+    );
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, true, &WalkOptions::default())
+        .unwrap();
+
+    let root = canonicalize(&root).unwrap();
+    let (a, b) = (root.join("a.md"), root.join("sub/b.md"));
+    let backlinks = tree.backlinks();
+    assert_eq!(backlinks.len(), 2);
+    assert_eq!(backlinks[&a], vec![b.clone()]);
+    assert_eq!(backlinks[&b], vec![a.clone()]);
+    assert_eq!(tree.unresolved_links(), vec![(a, "missing".to_string())]);
+  }
+
+  #[test]
+  fn test_non_utf8_content() {
+    let root = tree_fixture("non_utf8", &[("good.md", "# Good\n")]);
+    // latin-1 encoded "café"
+    fs::write(root.join("legacy.md"), b"caf\xe9 #old\n").unwrap();
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, true, &WalkOptions::default())
+        .unwrap();
+
+    let mut notes = tree.children_vec();
+    notes.sort_by(|a, b| a.file_name().cmp(b.file_name()));
+    assert_eq!(notes.len(), 2);
+    let (good, legacy) = (&notes[0], &notes[1]);
+    assert_eq!(good.file_text.as_deref(), Some("# Good\n"));
+    assert_eq!(legacy.file_text.as_deref(), Some("caf\u{fffd} #old\n"));
+    assert_eq!(legacy.tags, vec!["old"]);
+    assert!(legacy.read_error.is_none());
+  }
+
+  #[test]
+  fn test_read_preview() {
+    let body = "lorem ipsum ".repeat(100_000);
+    let text = format!("---\ntitle: Big\n---\n{body}");
+    let root = tree_fixture("read_preview", &[("big.md", &text)]);
+    let tree = Tree::init(
+      root.to_str().unwrap(),
+      None,
+      ReadMode::Preview(200),
+      &WalkOptions::default(),
+    )
+    .unwrap();
+
+    let note = tree.children_vec().pop().unwrap();
+    let preview = note.file_text.unwrap();
+    assert_eq!(preview.chars().count(), 200);
+    assert!(body.starts_with(&preview));
+    // the size still comes from the metadata
+    assert_eq!(note.size, text.len() as u64);
+  }
+
+  #[test]
+  fn test_read_preview_bounded() {
+    use crate::tree::node::{read_preview, FRONTMATTER_CAP};
+    use std::io::{self, Cursor, Read};
+
+    struct Counted<R>(R, usize);
+    impl<R: Read> Read for Counted<R> {
+      fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.0.read(buf)?;
+        self.1 += len;
+        Ok(len)
+      }
+    }
+
+    // the bytes of a buffer fill on top of the limit at most
+    let bound = 200 * 4 + FRONTMATTER_CAP as usize + 8 * 1024;
+    let body = "lorem ipsum\n".repeat(100_000);
+    for opening in ["---\n", "+++\n", "{\n"] {
+      let text = format!("{opening}{body}");
+      let mut source = Counted(Cursor::new(text.as_bytes()), 0);
+      let preview = read_preview(&mut source, 200).unwrap();
+      assert!(source.1 <= bound, "{opening:?} read {} bytes", source.1);
+      // not closed, no frontmatter then
+      assert_eq!(preview.chars().count(), 200);
+      assert!(text.starts_with(&preview));
+    }
+
+    let text = format!("---\ntitle: Big\n---\n{body}");
+    let mut source = Counted(Cursor::new(text.as_bytes()), 0);
+    let preview = read_preview(&mut source, 200).unwrap();
+    assert!(source.1 <= bound);
+    assert!(body.starts_with(&preview));
+  }
+
+  #[test]
+  fn test_single_thread() {
+    let root = tree_fixture(
+      "single_thread",
+      &[("a/b/c.md", "c"), ("a/d.md", "d"), ("e.md", "e")],
+    );
+    for threads in [Some(0), Some(1)] {
+      let opts = WalkOptions {
+        threads,
+        ..WalkOptions::default()
+      };
+      let tree = Tree::init(root.to_str().unwrap(), None, false, &opts).unwrap();
+
+      let mut names: Vec<String> = tree
+        .iter()
+        .map(|node| node.file_name_lossy().to_string())
+        .collect();
+      names.sort();
+      assert_eq!(
+        names,
+        vec!["a", "b", "c.md", "d.md", "e.md", "single_thread"]
+      );
+      assert_eq!(tree.traversal_stats().unwrap().threads_used, 1);
+    }
+
+    // an explicit count is taken as is up to 4 per core
+    let cores = std::thread::available_parallelism().unwrap().get();
+    for (threads, used) in [(cores * 2, cores * 2), (10_000, cores * 4)] {
+      let opts = WalkOptions {
+        threads: Some(threads),
+        ..WalkOptions::default()
+      };
+      let tree = Tree::init(root.to_str().unwrap(), None, false, &opts).unwrap();
+      assert_eq!(tree.traversal_stats().unwrap().threads_used, used);
+    }
+  }
+
+  #[test]
+  fn test_init_with_progress() {
+    let root = generated_fixture("init_with_progress", 300, |i| {
+      (format!("note{i}.md"), String::new())
+    });
+
+    let reports = std::sync::Mutex::new(Vec::new());
+    let tree = Tree::init_with_progress(
+      root.to_str().unwrap(),
+      None,
+      false,
+      &WalkOptions::default(),
+      |count| reports.lock().unwrap().push(count),
+    )
+    .unwrap();
+
+    assert_eq!(tree.iter().count(), 301);
+    assert_eq!(reports.into_inner().unwrap(), vec![256, 301]);
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn test_init_with_errors() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let root = tree_fixture(
+      "init_with_errors",
+      &[("locked/secret.md", "s"), ("open/a.md", "a"), ("b.md", "b")],
+    );
+    let locked = root.join("locked");
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+    // permissions don't apply to root
+    if fs::read_dir(&locked).is_ok() {
+      fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+      return;
+    }
+
+    let res = Tree::init_with_errors(
+      root.to_str().unwrap(),
+      None,
+      false,
+      &WalkOptions::default(),
+    );
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+    let (tree, errors) = res.unwrap();
+
+    let mut names: Vec<String> = tree
+      .iter()
+      .map(|node| node.file_name_lossy().to_string())
+      .collect();
+    names.sort();
+    assert_eq!(
+      names,
+      vec!["a.md", "b.md", "init_with_errors", "locked", "open"]
+    );
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].0, canonicalize(&locked).unwrap());
+  }
+
+  #[test]
+  fn test_traversal_panicked() {
+    let root = generated_fixture("traversal_panicked", 300, |i| {
+      (format!("note{i}.md"), String::new())
+    });
+
+    // the collector dies and drops its receiver while walkers still send
+    let res = Tree::init_with_progress(
+      root.to_str().unwrap(),
+      None,
+      false,
+      &WalkOptions::default(),
+      |_| panic!("progress callback failed"),
+    );
+    assert!(matches!(res, Err(TreeError::TraversalPanicked)));
+  }
+
+  #[test]
+  fn test_assemble_deep_tree() {
+    use indextree::Arena;
+    use std::collections::HashMap;
+
+    const DEPTH: usize = 20_000;
+    // the assembler only looks up children by path, no need for real ones
+    let mut arena = Arena::new();
+    let mut branches: HashMap<PathBuf, Vec<_>> = HashMap::new();
+    let ids: Vec<_> = (0..=DEPTH)
+      .map(|i| {
+        let path = PathBuf::from(format!("/chain/{i}"));
+        arena.new_node(Node::new(i, format!("{i}").into(), None, None, None, path))
+      })
+      .collect();
+    for i in 1..=DEPTH {
+      branches.insert(PathBuf::from(format!("/chain/{}", i - 1)), vec![ids[i]]);
+    }
+    // a cycle back to the root is cut off
+    branches.insert(PathBuf::from(format!("/chain/{DEPTH}")), vec![ids[0]]);
+
+    Tree::assemble_tree(&mut arena, ids[0], &mut branches);
+
+    let tree = Tree::new(arena, ids[0]);
+    assert_eq!(tree.iter().count(), DEPTH + 1);
+    assert_eq!(tree.iter().last().unwrap().depth, DEPTH);
+  }
+
+  #[test]
+  fn test_max_nodes() {
+    let root =
+      generated_fixture("max_nodes", 20, |i| (format!("note{i}.md"), String::new()));
+    let opts = WalkOptions {
+      max_nodes: Some(5),
+      ..WalkOptions::default()
+    };
+
+    let tree = Tree::init(root.to_str().unwrap(), None, false, &opts).unwrap();
+    assert_eq!(tree.iter().count(), 5);
+    assert!(tree.truncated());
+
+    let opts = WalkOptions {
+      max_nodes: Some(21),
+      ..WalkOptions::default()
+    };
+    let tree = Tree::init(root.to_str().unwrap(), None, false, &opts).unwrap();
+    assert_eq!(tree.iter().count(), 21);
+    assert!(!tree.truncated());
+  }
+
+  #[test]
+  fn test_prune_empty() {
+    let root = tree_fixture(
+      "prune_empty",
+      &[("a/b/c/", ""), ("a/note.md", "n"), ("d/e.txt", "e")],
+    );
+    let mut tree = Tree::init_markdown(root.to_str().unwrap(), None, false).unwrap();
+
+    assert_eq!(tree.prune_empty(), 3);
+    let mut names: Vec<String> = tree
+      .iter()
+      .map(|node| node.file_name_lossy().to_string())
+      .collect();
+    names.sort();
+    assert_eq!(names, vec!["a", "note.md", "prune_empty"]);
+
+    let opts = WalkOptions {
+      markdown_only: true,
+      prune_empty_dirs: true,
+      ..WalkOptions::default()
+    };
+    let tree = Tree::init(root.to_str().unwrap(), None, false, &opts).unwrap();
+    assert_eq!(tree.iter().count(), 3);
+  }
+
+  #[test]
+  fn test_create() {
+    let root = tree_fixture("create", &[("a.md", "a")]);
+    let mut tree =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+
+    let root_id = tree.root;
+    let note_id = tree.create(root_id, "new.md", false).unwrap();
+    let dir_id = tree.create(root_id, "folder", true).unwrap();
+    assert!(root.join("new.md").is_file());
+    assert!(root.join("folder").is_dir());
+    assert_eq!(tree.inner()[note_id].get().file_name(), "new.md");
+    assert!(tree.inner()[dir_id].get().is_dir());
+    assert_eq!(tree.find_by_path(&root.join("new.md")), Some(note_id));
+
+    let nested = tree.create(dir_id, "inner.md", false).unwrap();
+    assert_eq!(tree.inner()[nested].get().depth, 2);
+
+    assert!(matches!(
+      tree.create(root_id, "a.md", false),
+      Err(TreeError::AlreadyExists(_))
+    ));
+    for name in ["sub/x.md", "..", "", "./x.md"] {
+      assert!(matches!(
+        tree.create(root_id, name, false),
+        Err(TreeError::InvalidName(_))
+      ));
+    }
+  }
+
+  #[cfg(any(target_os = "linux", target_os = "macos", target_os = "windows"))]
+  #[test]
+  #[ignore = "moves the fixture into the trash of the user"]
+  fn test_trash() {
+    let root = tree_fixture(
+      "trash",
+      &[
+        ("keep.md", "k"),
+        ("folder/a.md", "a"),
+        ("folder/sub/b.md", "b"),
+      ],
+    );
+    let mut tree =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+
+    let folder = tree.find_by_path(&root.join("folder")).unwrap();
+    tree.trash(folder).unwrap();
+
+    assert!(!root.join("folder").exists());
+    assert!(root.join("keep.md").exists());
+    assert!(tree.find_by_path(&root.join("folder/sub/b.md")).is_none());
+    assert_eq!(tree.iter().count(), 2);
+    assert!(matches!(
+      tree.trash(tree.root),
+      Err(TreeError::ExpectedParent(_))
+    ));
+  }
+
+  #[test]
+  fn test_rename() {
+    let root = tree_fixture(
+      "rename",
+      &[
+        ("a/b/c.md", "c"),
+        ("a/d.md", "d"),
+        ("x/", ""),
+        ("note.md", "n"),
+      ],
+    );
+    let mut tree =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+
+    // in place
+    let note = tree.find_by_path(&root.join("note.md")).unwrap();
+    tree.rename(note, &root.join("renamed.md")).unwrap();
+    assert!(root.join("renamed.md").is_file());
+    assert_eq!(tree.inner()[note].get().file_name(), "renamed.md");
+    assert_eq!(tree.find_by_path(&root.join("renamed.md")), Some(note));
+    assert!(tree.find_by_path(&root.join("note.md")).is_none());
+
+    // a folder into another one
+    let a = tree.find_by_path(&root.join("a")).unwrap();
+    let x = tree.find_by_path(&root.join("x")).unwrap();
+    let c = tree.find_by_path(&root.join("a/b/c.md")).unwrap();
+    tree.rename(a, &root.join("x/moved")).unwrap();
+    assert!(root.join("x/moved/b/c.md").is_file());
+    assert_eq!(tree.inner()[a].parent(), Some(x));
+    assert_eq!(tree.find_by_path(&root.join("x/moved/b/c.md")), Some(c));
+    assert!(tree.find_by_path(&root.join("a/b/c.md")).is_none());
+    let moved_c = tree.inner()[c].get();
+    assert_eq!(
+      moved_c.path(),
+      canonicalize(root.join("x/moved/b/c.md")).unwrap()
+    );
+    assert_eq!(moved_c.depth, 4);
+
+    assert!(matches!(
+      tree.rename(x, &root.join("renamed.md")),
+      Err(TreeError::AlreadyExists(_))
+    ));
+  }
+
+  #[test]
+  fn test_rename_extension() {
+    let root =
+      tree_fixture("rename_extension", &[("a.md", "# a"), ("img.png", "i")]);
+    let mut tree =
+      Tree::init(root.to_str().unwrap(), None, true, &WalkOptions::default())
+        .unwrap();
+
+    let a = tree.find_by_path(&root.join("a.md")).unwrap();
+    tree.rename(a, &root.join("a.png")).unwrap();
+    let node = tree.inner()[a].get();
+    assert_eq!(node.kind(), FileKind::Image);
+    assert_eq!(node.file_text, None);
+
+    let img = tree.find_by_path(&root.join("img.png")).unwrap();
+    assert_eq!(tree.inner()[img].get().file_text, None);
+    tree.rename(img, &root.join("img.md")).unwrap();
+    let node = tree.inner()[img].get();
+    assert_eq!(node.kind(), FileKind::Markdown);
+    assert_eq!(node.file_text.as_deref(), Some("i"));
+  }
+
+  #[test]
+  fn test_node_kind() {
+    let root = tree_fixture(
+      "node_kind",
+      &[
+        ("note.md", ""),
+        ("photo.PNG", ""),
+        ("song.mp3", ""),
+        ("paper.pdf", ""),
+        ("todo.txt", ""),
+        ("blob", ""),
+        ("folder/", ""),
+      ],
+    );
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+
+    let kind_of = |rel: &str| {
+      let id = tree.find_by_path(&root.join(rel)).unwrap();
+      tree.inner()[id].get().kind()
+    };
+    assert_eq!(kind_of("note.md"), FileKind::Markdown);
+    assert_eq!(kind_of("photo.PNG"), FileKind::Image);
+    assert_eq!(kind_of("song.mp3"), FileKind::Audio);
+    assert_eq!(kind_of("paper.pdf"), FileKind::Pdf);
+    assert_eq!(kind_of("todo.txt"), FileKind::Text);
+    assert_eq!(kind_of("blob"), FileKind::Other);
+    assert_eq!(kind_of("folder"), FileKind::Directory);
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn test_node_is_symlink() {
+    let root = tree_fixture("node_is_symlink", &[("a/x.md", "x"), ("b.md", "b")]);
+    std::os::unix::fs::symlink(root.join("b.md"), root.join("file_link.md"))
+      .unwrap();
+    // outside of the root, a second path to `a` would be deduplicated
+    let target = tree_fixture("node_is_symlink_target", &[("y.md", "y")]);
+    std::os::unix::fs::symlink(target, root.join("dir_link")).unwrap();
+    let opts = WalkOptions {
+      follow_links: true,
+      ..WalkOptions::default()
+    };
+    let tree = Tree::init(root.to_str().unwrap(), None, false, &opts).unwrap();
+
+    let node = |rel: &str| {
+      let canonical = canonicalize(&root).unwrap().join(rel);
+      tree
+        .iter()
+        .find(|node| node.path() == canonical)
+        .unwrap()
+        .clone()
+    };
+    assert!(node("file_link.md").is_symlink());
+    assert!(!node("b.md").is_symlink());
+    assert!(!node("a").is_symlink());
+    // followed, yet still marked
+    let dir_link = node("dir_link");
+    assert!(dir_link.is_symlink() && dir_link.is_dir());
+    assert!(!node("dir_link/y.md").is_symlink());
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn test_reindex_followed_links() {
+    let root = tree_fixture("reindex_links", &[("b.md", "# b")]);
+    std::os::unix::fs::symlink(root.join("b.md"), root.join("link.md")).unwrap();
+    let target = tree_fixture("reindex_links_target", &[("y.md", "y")]);
+    std::os::unix::fs::symlink(target, root.join("dir_link")).unwrap();
+    let opts = WalkOptions {
+      follow_links: true,
+      ..WalkOptions::default()
+    };
+    let mut tree = Tree::init(root.to_str().unwrap(), None, true, &opts).unwrap();
+    let root = canonicalize(&root).unwrap();
+
+    let link = root.join("link.md");
+    tree.reindex_path(&link).unwrap();
+    let node = tree.inner()[tree.find_by_path(&link).unwrap()].get();
+    assert_eq!(node.file_text.as_deref(), Some("# b"));
+    assert!(node.is_symlink());
+
+    let dir_link = root.join("dir_link");
+    tree.reindex_path(&dir_link).unwrap();
+    let dir_id = tree.find_by_path(&dir_link).unwrap();
+    let node = tree.inner()[dir_id].get();
+    assert!(node.is_dir() && node.is_symlink());
+    assert_eq!(dir_id.children(tree.inner()).count(), 1);
+  }
+
+  #[test]
+  fn test_flatten() {
+    let root = tree_fixture(
+      "flatten",
+      &[("b/d.md", "d"), ("b/c.md", "c"), ("a.md", "a"), ("e/", "")],
+    );
+    let mut tree =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+    tree.sort_children(SortKey::Name, true).unwrap();
+
+    let root = canonicalize(&root).unwrap();
+    let files = ["a.md", "b/c.md", "b/d.md"].map(|rel| root.join(rel));
+    assert_eq!(tree.flatten(), files);
+    // paths compare by components, `root/` is the root
+    let all = ["", "a.md", "b", "b/c.md", "b/d.md", "e"].map(|rel| root.join(rel));
+    assert_eq!(tree.flatten_all(), all);
+  }
+
+  #[test]
+  fn test_to_markdown_outline() {
+    let root = tree_fixture(
+      "markdown_outline",
+      &[
+        ("a/b/c.md", "c"),
+        ("a/d.md", "d"),
+        ("e/", ""),
+        ("f.md", "f"),
+      ],
+    );
+    let mut tree =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+    tree.sort_children(SortKey::Name, true).unwrap();
+
+    let expected = "\
+- markdown_outline/
+  - a/
+    - b/
+      - c.md
+    - d.md
+  - e/
+  - f.md
+";
+    assert_eq!(tree.to_markdown_outline(), expected);
+  }
+
+  #[test]
+  fn test_to_ascii() {
+    let root = tree_fixture(
+      "ascii",
+      &[
+        ("a/b/c.md", "c"),
+        ("a/d.md", "d"),
+        ("e/f.md", ""),
+        ("g.md", "g"),
+      ],
+    );
+    let mut tree =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+    tree.sort_children(SortKey::Name, true).unwrap();
+
+    let expected = "\
+ascii
+├── a
+│   ├── b
+│   │   └── c.md
+│   └── d.md
+├── e
+│   └── f.md
+└── g.md
+";
+    assert_eq!(tree.to_ascii(), expected);
+  }
+
+  #[test]
+  fn test_find_by_name() {
+    let root = tree_fixture(
+      "find_by_name",
+      &[
+        ("note.md", ""),
+        ("a/Note-2.md", ""),
+        ("a/b/old_notes/", ""),
+        ("a/b/other.md", ""),
+      ],
+    );
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+    let names = |ids: Vec<_>| {
+      let mut names: Vec<String> = ids
+        .into_iter()
+        .map(|id| tree.inner()[id].get().file_name_lossy().to_string())
+        .collect();
+      names.sort();
+      names
+    };
+
+    assert_eq!(
+      names(tree.find_by_name("note", false)),
+      vec!["note.md", "old_notes"]
+    );
+    assert_eq!(
+      names(tree.find_by_name("note", true)),
+      vec!["Note-2.md", "note.md", "old_notes"]
+    );
+    // the full name is matched
+    assert_eq!(names(tree.find_by_name("e.md", false)), vec!["note.md"]);
+  }
+
+  #[test]
+  fn test_fuzzy_find() {
+    let root = tree_fixture(
+      "fuzzy_find",
+      &[
+        ("tree/mod.rs", ""),
+        ("tree/node.rs", ""),
+        ("readme.md", ""),
+        ("notes/todo.md", ""),
+      ],
+    );
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+
+    let hits = tree.fuzzy_find("trmd", 10);
+    let best = tree.find_by_path(&root.join("tree/mod.rs")).unwrap();
+    assert_eq!(hits.first().map(|(id, _)| *id), Some(best));
+    // no subsequence in the others
+    assert_eq!(hits.len(), 1);
+
+    let hits = tree.fuzzy_find("md", 2);
+    assert_eq!(hits.len(), 2);
+    assert!(hits[0].1 >= hits[1].1);
+  }
+
+  #[test]
+  fn test_find_by_regex() {
+    let root = tree_fixture(
+      "find_by_regex",
+      &[
+        ("daily/2024-01-31.md", ""),
+        ("daily/2024-02-01 standup.md", ""),
+        ("daily/2024-02.md", ""),
+        ("notes/x2024-01-31.md", ""),
+        ("notes/2024-01-31.txt", ""),
+      ],
+    );
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+
+    let mut names: Vec<String> = tree
+      .find_by_regex(r"^\d{4}-\d{2}-\d{2}.*\.md$")
+      .unwrap()
+      .into_iter()
+      .map(|id| tree.inner()[id].get().file_name_lossy().to_string())
+      .collect();
+    names.sort();
+    assert_eq!(names, vec!["2024-01-31.md", "2024-02-01 standup.md"]);
+
+    assert!(matches!(
+      tree.find_by_regex("(unclosed"),
+      Err(TreeError::InvalidRegex(_))
+    ));
+  }
+
+  #[test]
+  fn test_search_content() {
+    let text = "# Title\n\nsome intro\nthe Quick brown fox jumps\nend\n";
+    let long = format!("{}needle{}\n", "a".repeat(60), "b".repeat(60));
+    let root = tree_fixture(
+      "search_content",
+      &[("a.md", text), ("sub/long.md", &long), ("b.txt", "quick")],
+    );
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, true, &WalkOptions::default())
+        .unwrap();
+    let a = tree.find_by_path(&root.join("a.md")).unwrap();
+
+    let hits = tree.search_content("quick brown", true);
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].node, a);
+    assert_eq!(hits[0].line, 4);
+    assert_eq!(hits[0].snippet, "the Quick brown fox jumps");
+    assert!(tree.search_content("quick brown", false).is_empty());
+
+    let hits = tree.search_content("needle", false);
+    let expected = format!("…{}needle{}…", "a".repeat(40), "b".repeat(40));
+    assert_eq!(hits[0].snippet, expected);
+
+    // content not loaded during the traversal is read lazily
+    let lazy =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+    let hits = lazy.search_content("brown", false);
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].line, 4);
+
+    // also from a cache, which keeps no metadata
+    let cache = root.with_extension("json");
+    lazy.save_cache(&cache).unwrap();
+    let loaded = Tree::load_cache(&cache).unwrap();
+    fs::remove_file(&cache).unwrap();
+    let hits = loaded.search_content("brown", false);
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].line, 4);
+  }
+
+  #[test]
+  fn test_content_hash() {
+    let root = tree_fixture(
+      "content_hash",
+      &[("note.md", "first"), ("same.md", "first")],
+    );
+    let hash_of = |rel: &str| {
+      let tree =
+        Tree::init(root.to_str().unwrap(), None, true, &WalkOptions::default())
+          .unwrap();
+      let id = tree.find_by_path(&root.join(rel)).unwrap();
+      tree.inner()[id].get().content_hash
+    };
+
+    let before = hash_of("note.md");
+    assert!(before.is_some());
+    assert_eq!(before, hash_of("same.md"));
+    fs::write(root.join("note.md"), "second").unwrap();
+    let after = hash_of("note.md");
+    assert!(after.is_some());
+    assert_ne!(before, after);
+
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+    assert!(tree.iter().all(|node| node.content_hash.is_none()));
+  }
+
+  #[test]
+  fn test_find_duplicates() {
+    let root = tree_fixture(
+      "find_duplicates",
+      &[
+        ("shot.png", "same bytes"),
+        ("copies/shot (1).png", "same bytes"),
+        ("other.png", "diff bytes"),
+        ("unique.md", "unique"),
+        ("empty1.md", ""),
+        ("empty2.md", ""),
+      ],
+    );
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+
+    let expected = vec![vec![
+      tree
+        .find_by_path(&root.join("copies/shot (1).png"))
+        .unwrap(),
+      tree.find_by_path(&root.join("shot.png")).unwrap(),
+    ]];
+    assert_eq!(tree.find_duplicates(), expected);
+
+    // a hash of the read content matches one streamed from disk
+    let root = tree_fixture(
+      "find_duplicates_read",
+      &[("note.md", "same text"), ("note.bak", "same text")],
+    );
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, true, &WalkOptions::default())
+        .unwrap();
+    let note = tree.find_by_path(&root.join("note.md")).unwrap();
+    assert!(tree.inner()[note].get().content_hash.is_some());
+    assert_eq!(tree.find_duplicates().len(), 1);
+  }
+
+  #[test]
+  fn test_node_is_binary() {
+    let root = tree_fixture("node_is_binary", &[("note.md", "# Text\n")]);
+    fs::write(
+      root.join("photo.png"),
+      b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR\0\0\0\x01\0\0\0\x01\x08\x02",
+    )
+    .unwrap();
+    let utf16 = |bom: &[u8]| {
+      let mut bytes = bom.to_vec();
+      bytes.extend("utf-16 text".encode_utf16().flat_map(u16::to_le_bytes));
+      bytes
+    };
+    fs::write(root.join("bom.txt"), utf16(&[0xff, 0xfe])).unwrap();
+    fs::write(root.join("no_bom.txt"), utf16(&[])).unwrap();
+    fs::write(root.join("latin1.txt"), b"caf\xe9").unwrap();
+
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, true, &WalkOptions::default())
+        .unwrap();
+    let is_binary = |rel: &str| {
+      let id = tree.find_by_path(&root.join(rel)).unwrap();
+      tree.inner()[id].get().is_binary
+    };
+    assert!(is_binary("photo.png"));
+    assert!(!is_binary("note.md"));
+    assert!(!is_binary("bom.txt"));
+    assert!(!is_binary("no_bom.txt"));
+    assert!(!is_binary("latin1.txt"));
+  }
+
+  #[cfg(feature = "async")]
+  #[tokio::test]
+  async fn test_init_async() {
+    let root = tree_fixture("init_async", &[("a/b.md", "b"), ("c.md", "c")]);
+    let tree =
+      Tree::init_async(root.to_str().unwrap(), None, true, &WalkOptions::default())
+        .await
+        .unwrap();
+
+    assert_eq!(tree.iter().count(), 4);
+    assert!(tree.find_by_path(&root.join("a/b.md")).is_some());
+  }
+
+  #[test]
+  fn test_init_cancellable() {
+    use std::sync::{
+      atomic::{AtomicBool, Ordering},
+      Arc,
+    };
+
+    let root = generated_fixture("init_cancellable", 50, |i| {
+      (format!("dir{}/note{i}.md", i % 5), String::new())
+    });
+    let dir = root.to_str().unwrap();
+    let opts = WalkOptions::default();
+
+    let cancel = Arc::new(AtomicBool::new(false));
+    let tree = Tree::init_cancellable(dir, None, false, &opts, cancel.clone());
+    assert_eq!(tree.unwrap().iter().count(), 56);
+
+    // set before the walk, only the root gets through
+    cancel.store(true, Ordering::Relaxed);
+    let started = std::time::Instant::now();
+    match Tree::init_cancellable(dir, None, false, &opts, cancel) {
+      Err(TreeError::Cancelled(partial)) => {
+        assert_eq!(partial.iter().count(), 1);
+      }
+      other => panic!("expected Cancelled, got {other:?}"),
+    }
+    assert!(started.elapsed() < std::time::Duration::from_secs(5));
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn test_init_lenient() {
+    let root = tree_fixture("init_lenient", &[("a/b.md", "b"), ("c.md", "c")]);
+    // can't be followed
+    std::os::unix::fs::symlink(root.join("gone.md"), root.join("a/broken.md"))
+      .unwrap();
+    let opts = WalkOptions {
+      follow_links: true,
+      ..WalkOptions::default()
+    };
+
+    let (tree, errors) =
+      Tree::init_lenient(root.to_str().unwrap(), None, true, &opts).unwrap();
+    let mut names: Vec<String> = tree
+      .iter()
+      .map(|node| node.file_name_lossy().to_string())
+      .collect();
+    names.sort();
+    assert_eq!(names, vec!["a", "b.md", "c.md", "init_lenient"]);
+    assert_eq!(errors.len(), 1);
+    let broken = canonicalize(&root).unwrap().join("a/broken.md");
+    assert!(matches!(&errors[0], TreeError::NotFound(path) if *path == broken));
+  }
+
+  #[test]
+  fn test_custom_ignore_files() {
+    let root = tree_fixture(
+      "custom_ignore_files",
+      &[
+        (".mdignore", "drafts/\n*.tmp.md\n!keep.tmp.md\n"),
+        ("drafts/wip.md", ""),
+        ("a.tmp.md", ""),
+        ("keep.tmp.md", ""),
+        ("note.md", ""),
+      ],
+    );
+    let opts = WalkOptions {
+      custom_ignore_files: vec![".mdignore".to_string()],
+      ..WalkOptions::default()
+    };
+    let tree = Tree::init(root.to_str().unwrap(), None, false, &opts).unwrap();
+
+    let mut names: Vec<String> = tree
+      .children_vec()
+      .iter()
+      .map(|node| node.file_name_lossy().to_string())
+      .collect();
+    names.sort();
+    assert_eq!(names, vec!["keep.tmp.md", "note.md"]);
+  }
+
+  #[test]
+  fn test_init_multi() {
+    let work = tree_fixture("init_multi_work", &[("todo.md", "")]);
+    let personal = tree_fixture("init_multi_personal", &[("diary/day.md", "")]);
+    let tree = Tree::init_multi(
+      &[work.to_str().unwrap(), personal.to_str().unwrap()],
+      &WalkOptions::default(),
+    )
+    .unwrap();
+
+    assert!(tree.inner()[tree.root].get().is_virtual());
+    let mut names: Vec<String> = tree
+      .children_vec()
+      .iter()
+      .map(|node| node.file_name_lossy().to_string())
+      .collect();
+    names.sort();
+    assert_eq!(names, vec!["init_multi_personal", "init_multi_work"]);
+
+    let day = tree.find_by_path(&personal.join("diary/day.md")).unwrap();
+    assert_eq!(tree.inner()[day].get().depth, 3);
+    assert!(tree.find_by_path(&work.join("todo.md")).is_some());
+  }
+
+  #[test]
+  fn test_diff() {
+    let root = tree_fixture(
+      "diff",
+      &[("keep.md", "same"), ("edit.md", "before"), ("gone.md", "")],
+    );
+    let before =
+      Tree::init(root.to_str().unwrap(), None, true, &WalkOptions::default())
+        .unwrap();
+
+    fs::write(root.join("edit.md"), "after").unwrap();
+    fs::remove_file(root.join("gone.md")).unwrap();
+    fs::create_dir(root.join("new")).unwrap();
+    fs::write(root.join("new/note.md"), "").unwrap();
+    let after =
+      Tree::init(root.to_str().unwrap(), None, true, &WalkOptions::default())
+        .unwrap();
+
+    let diff = before.diff(&after);
+    assert_eq!(
+      diff.added,
+      vec![PathBuf::from("new"), PathBuf::from("new/note.md")]
+    );
+    assert_eq!(diff.removed, vec![PathBuf::from("gone.md")]);
+    assert_eq!(diff.modified, vec![PathBuf::from("edit.md")]);
+    assert_eq!(after.diff(&after), TreeDiff::default());
+  }
+
+  #[test]
+  fn test_relative_path() {
+    let root = tree_fixture("relative_path", &[("sub/dir/file.md", "")]);
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+
+    let file = tree.find_by_path(&root.join("sub/dir/file.md")).unwrap();
+    assert_eq!(
+      tree.relative_path(file),
+      Some(Path::new("sub").join("dir").join("file.md"))
+    );
+    assert_eq!(tree.relative_path(tree.root), Some(PathBuf::new()));
+
+    let forest =
+      Tree::init_multi(&[root.to_str().unwrap()], &WalkOptions::default()).unwrap();
+    let file = forest.find_by_path(&root.join("sub/dir/file.md")).unwrap();
+    assert_eq!(
+      forest.relative_path(file),
+      Some(Path::new("relative_path").join("sub/dir/file.md"))
+    );
+  }
+
+  #[cfg(target_os = "windows")]
+  #[test]
+  fn test_strip_verbatim() {
+    use crate::paths::strip_verbatim;
+
+    let disk = strip_verbatim(PathBuf::from(r"\\?\C:\md\silo"));
+    assert_eq!(disk, PathBuf::from(r"C:\md\silo"));
+    let unc = strip_verbatim(PathBuf::from(r"\\?\UNC\server\share\md"));
+    assert_eq!(unc, PathBuf::from(r"\\server\share\md"));
+    let dots = PathBuf::from(r"\\?\C:\md\..\silo");
+    assert_eq!(strip_verbatim(dots.clone()), dots);
+
+    let root = tree_fixture("strip_verbatim", &[("sub/note.md", "")]);
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+    for node in tree.iter() {
+      assert!(!node.path().to_string_lossy().starts_with(r"\\?\"));
+    }
+  }
+
+  #[test]
+  fn test_stream() {
+    let root = tree_fixture(
+      "stream",
+      &[("a.md", ""), ("sub/b.md", ""), ("sub/c.md", "")],
+    );
+    let rx = Tree::stream(root.to_str().unwrap(), &WalkOptions::default()).unwrap();
+
+    let mut names: Vec<String> = rx
+      .iter()
+      .map(|node| node.file_name_lossy().to_string())
+      .collect();
+    names.sort();
+    assert_eq!(names, vec!["a.md", "b.md", "c.md", "stream", "sub"]);
+  }
+
+  #[test]
+  fn test_cache() {
+    let root = tree_fixture(
+      "cache",
+      &[
+        ("a.md", "# A\n#tag [[b]]"),
+        ("sub/b.md", "b"),
+        ("sub/deep/", ""),
+      ],
+    );
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, true, &WalkOptions::default())
+        .unwrap();
+    let cache = root.with_extension("json");
+    tree.save_cache(&cache).unwrap();
+
+    let loaded = Tree::load_cache(&cache).unwrap();
+    assert_eq!(loaded.to_serializable(), tree.to_serializable());
+    let a = loaded.find_by_path(&root.join("a.md")).unwrap();
+    let a = loaded.inner()[a].get();
+    assert_eq!(a.file_text.as_deref(), Some("# A\n#tag [[b]]"));
+    assert_eq!(a.tags, vec!["tag"]);
+    assert!(!loaded.is_stale());
+
+    fs::remove_dir_all(&root).unwrap();
+    assert!(loaded.is_stale());
+    fs::remove_file(&cache).unwrap();
+  }
+
+  #[test]
+  fn test_content_concurrency() {
+    let root = generated_fixture("content_concurrency", 20, |i| {
+      (format!("dir{}/note{i}.md", i % 4), format!("note {i}"))
+    });
+    let opts = WalkOptions {
+      threads: Some(4),
+      content_concurrency: Some(1),
+      ..WalkOptions::default()
+    };
+    let tree = Tree::init(root.to_str().unwrap(), None, true, &opts).unwrap();
+
+    let notes: Vec<&Node> = tree.iter().filter(|node| !node.is_dir()).collect();
+    assert_eq!(notes.len(), 20);
+    for node in notes {
+      let stem = node.path().file_stem().unwrap().to_string_lossy();
+      let expected = format!("note {}", stem.trim_start_matches("note"));
+      assert_eq!(node.file_text.as_deref(), Some(expected.as_str()));
+    }
+  }
+
+  #[test]
+  fn test_expand() {
+    let root = tree_fixture(
+      "expand",
+      &[
+        ("a.md", ""),
+        ("big/b.md", ""),
+        ("big/c.txt", ""),
+        ("big/inner/d.md", ""),
+      ],
+    );
+    let opts = WalkOptions {
+      lazy_dirs: true,
+      exclude_globs: vec!["big/*.txt".to_string()],
+      ..WalkOptions::default()
+    };
+    let mut tree = Tree::init(root.to_str().unwrap(), None, false, &opts).unwrap();
+    assert!(tree.inner()[tree.root].get().is_loaded());
+
+    let big = tree.find_by_path(&root.join("big")).unwrap();
+    assert!(!tree.inner()[big].get().is_loaded());
+    assert_eq!(big.children(tree.inner()).count(), 0);
+
+    let children = tree.expand(big).unwrap();
+    let mut names: Vec<String> = children
+      .iter()
+      .map(|id| tree.inner()[*id].get().file_name_lossy().to_string())
+      .collect();
+    names.sort();
+    assert_eq!(names, vec!["b.md", "inner"]);
+    assert!(tree.inner()[big].get().is_loaded());
+
+    let inner = tree.find_by_path(&root.join("big/inner")).unwrap();
+    assert_eq!(tree.inner()[inner].get().depth, 2);
+    assert!(!tree.inner()[inner].get().is_loaded());
+    assert_eq!(tree.expand(big).unwrap().len(), 2);
+  }
+
+  #[test]
+  fn test_node_uid() {
+    let root = tree_fixture(
+      "node_uid",
+      &[("a.md", ""), ("sub/a.md", ""), ("sub/b/", "")],
+    );
+    let uids = || {
+      let tree =
+        Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+          .unwrap();
+      let mut uids: Vec<(PathBuf, u64)> = tree
+        .iter()
+        .map(|node| (node.path().to_owned(), node.uid()))
+        .collect();
+      uids.sort();
+      uids
+    };
+
+    let first = uids();
+    assert_eq!(first, uids());
+    let mut distinct: Vec<u64> = first.iter().map(|(_, uid)| *uid).collect();
+    distinct.sort();
+    distinct.dedup();
+    assert_eq!(distinct.len(), 4);
+  }
+
+  #[test]
+  fn test_diff_renamed() {
+    let root = tree_fixture(
+      "diff_renamed",
+      &[
+        ("old.md", "moving note"),
+        ("sub/", ""),
+        ("a.md", ""),
+        ("b.md", "x"),
+      ],
+    );
+    let before =
+      Tree::init(root.to_str().unwrap(), None, true, &WalkOptions::default())
+        .unwrap();
+
+    fs::rename(root.join("old.md"), root.join("sub/new.md")).unwrap();
+    fs::rename(root.join("a.md"), root.join("c.md")).unwrap();
+    let after =
+      Tree::init(root.to_str().unwrap(), None, true, &WalkOptions::default())
+        .unwrap();
+
+    let diff = before.diff(&after);
+    assert_eq!(
+      diff.renamed,
+      vec![Renamed {
+        from: PathBuf::from("old.md"),
+        to: Path::new("sub").join("new.md"),
+      }]
+    );
+    // empty files aren't told apart
+    assert_eq!(diff.removed, vec![PathBuf::from("a.md")]);
+    assert_eq!(diff.added, vec![PathBuf::from("c.md")]);
+    assert!(diff.modified.is_empty());
+  }
+
+  #[test]
+  fn test_encoding() {
+    let root = tree_fixture("encoding", &[]);
+    let mut utf16 = vec![0xff, 0xfe];
+    utf16.extend("# Café\nnote".encode_utf16().flat_map(u16::to_le_bytes));
+    fs::write(root.join("utf16.md"), utf16).unwrap();
+    // "naïve" in Windows-1252
+    fs::write(root.join("legacy.md"), b"na\xefve").unwrap();
+
+    let text_of = |tree: &Tree, name: &str| {
+      let id = tree.find_by_path(&root.join(name)).unwrap();
+      tree.inner()[id].get().file_text.clone().unwrap()
+    };
+
+    let opts = WalkOptions {
+      encoding: Encoding::Auto,
+      ..WalkOptions::default()
+    };
+    let tree = Tree::init(root.to_str().unwrap(), None, true, &opts).unwrap();
+    assert_eq!(text_of(&tree, "utf16.md"), "# Café\nnote");
+    assert_eq!(text_of(&tree, "legacy.md"), "naïve");
+    let utf16 = tree.find_by_path(&root.join("utf16.md")).unwrap();
+    assert_eq!(tree.inner()[utf16].get().title(), "Café");
+
+    let opts = WalkOptions {
+      encoding: Encoding::Other(encoding_rs::WINDOWS_1252),
+      ..WalkOptions::default()
+    };
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, ReadMode::Preview(3), &opts).unwrap();
+    assert_eq!(text_of(&tree, "legacy.md"), "naï");
+
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, true, &WalkOptions::default())
+        .unwrap();
+    assert_eq!(text_of(&tree, "legacy.md"), "na\u{fffd}ve");
+  }
+
+  #[test]
+  fn test_frontmatter_formats() {
+    let root = tree_fixture(
+      "frontmatter_formats",
+      &[
+        ("yaml.md", "---\ntitle: Same\n---\nbody"),
+        (
+          "toml.md",
+          "+++\ntitle = \"Same\"\ndate = 2024-01-02\n+++\nbody",
+        ),
+        ("json.md", "{\n  \"title\": \"Same\"\n}\nbody"),
+        ("bad_toml.md", "+++\ntitle = \n+++\nbody"),
+        ("bad_json.md", "{ \"title\": \nbody"),
+      ],
+    );
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, true, &WalkOptions::default())
+        .unwrap();
+    let node = |name: &str| {
+      let id = tree.find_by_path(&root.join(name)).unwrap();
+      tree.inner()[id].get().clone()
+    };
+
+    for name in ["yaml.md", "toml.md", "json.md"] {
+      let frontmatter = node(name).frontmatter.unwrap();
+      assert_eq!(frontmatter["title"].as_str(), Some("Same"), "{name}");
+      assert_eq!(node(name).word_count, 1, "{name}");
+    }
+    assert_eq!(
+      node("toml.md").frontmatter.unwrap()["date"].as_str(),
+      Some("2024-01-02")
+    );
+    assert!(node("bad_toml.md").frontmatter.is_none());
+    assert!(node("bad_json.md").frontmatter.is_none());
+
+    let tree = Tree::init(
+      root.to_str().unwrap(),
+      None,
+      ReadMode::Preview(4),
+      &WalkOptions::default(),
+    )
+    .unwrap();
+    for name in ["yaml.md", "toml.md", "json.md"] {
+      let id = tree.find_by_path(&root.join(name)).unwrap();
+      assert_eq!(tree.inner()[id].get().file_text.as_deref(), Some("body"));
+    }
+  }
+
+  #[test]
+  fn test_node_md_links() {
+    let text =
+      "See [other](../other/note.md) and [site](https://example.com \"Site\").\n\
+      ![chart](img/chart.png) `[code](skipped.md)` [again](../other/note.md)\n\
+      ```\n[fenced](skipped.md)\n```\n[spaced](<my note.md>)";
+    let root = tree_fixture("md_links", &[("note.md", text)]);
+    let node_at = |opts: &WalkOptions| {
+      let tree = Tree::init(root.to_str().unwrap(), None, true, opts).unwrap();
+      let id = tree.find_by_path(&root.join("note.md")).unwrap();
+      tree.inner()[id].get().md_links.clone()
+    };
+
+    assert_eq!(
+      node_at(&WalkOptions::default()),
+      vec!["../other/note.md", "https://example.com", "my note.md"]
+    );
+    let opts = WalkOptions {
+      image_links: true,
+      ..WalkOptions::default()
+    };
+    assert_eq!(
+      node_at(&opts),
+      vec![
+        "../other/note.md",
+        "https://example.com",
+        "img/chart.png",
+        "my note.md"
+      ]
+    );
+  }
+
+  #[test]
+  fn test_external_urls() {
+    let root = tree_fixture(
+      "external_urls",
+      &[
+        (
+          "links.md",
+          "Read [docs](https://example.com/docs) or <http://example.org>.\n\
+          Again https://example.com/docs, and `https://in.code`",
+        ),
+        ("none.md", "[local](other.md)"),
+      ],
+    );
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, true, &WalkOptions::default())
+        .unwrap();
+    let root = canonicalize(&root).unwrap();
+
+    let urls = tree.external_urls();
+    assert_eq!(urls.len(), 1);
+    assert_eq!(
+      urls[&root.join("links.md")],
+      vec!["https://example.com/docs", "http://example.org"]
+    );
+  }
+
+  #[test]
+  fn test_resolve_link() {
+    let root = tree_fixture(
+      "resolve_link",
+      &[
+        ("index.md", ""),
+        ("a/Index.md", ""),
+        ("a/b/c/note.md", ""),
+        ("a/b/index.md", ""),
+        ("d/note.md", ""),
+        ("e/f/only.md", ""),
+        ("g/only.md", ""),
+      ],
+    );
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+    let id = |rel: &str| tree.find_by_path(&root.join(rel)).unwrap();
+    let resolve = |from: &str, target: &str| tree.resolve_link(id(from), target);
+
+    assert_eq!(resolve("a/b/c/note.md", "index"), Some(id("a/b/index.md")));
+    assert_eq!(resolve("a/b/index.md", "INDEX"), Some(id("a/b/index.md")));
+    assert_eq!(resolve("d/note.md", "index"), Some(id("index.md")));
+    assert_eq!(resolve("a/b/c/note.md", "a/index"), Some(id("a/Index.md")));
+    // elsewhere: fewest components first
+    assert_eq!(resolve("d/note.md", "only"), Some(id("g/only.md")));
+    assert_eq!(resolve("d/note.md", "missing"), None);
+  }
+
+  #[test]
+  fn test_link_graph() {
+    let root = tree_fixture(
+      "link_graph",
+      &[
+        (
+          "a.md",
+          "# Alpha\n[[b]] and [c](sub/c%20note.md#top) [[a]] [[nowhere]]",
+        ),
+        ("b.md", "[back](a.md) [web](https://example.com)"),
+        ("sub/c note.md", "[[b]] [up](../a.md) [gone](../gone.md)"),
+      ],
+    );
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, true, &WalkOptions::default())
+        .unwrap();
+    let graph = tree.link_graph();
+
+    let edge = |from: &str, to: &str| GraphEdge {
+      from: PathBuf::from_slash(from),
+      to: PathBuf::from_slash(to),
+    };
+    assert_eq!(
+      graph.edges,
+      vec![
+        edge("a.md", "b.md"),
+        edge("a.md", "sub/c note.md"),
+        edge("b.md", "a.md"),
+        edge("sub/c note.md", "a.md"),
+        edge("sub/c note.md", "b.md"),
+      ]
+    );
+
+    let mut nodes: Vec<(PathBuf, String)> = graph
+      .nodes
+      .into_iter()
+      .map(|node| (node.path, node.title))
+      .collect();
+    nodes.sort();
+    assert_eq!(nodes[0], (PathBuf::from("a.md"), "Alpha".to_string()));
+    assert_eq!(nodes.len(), 3);
+  }
+
+  #[test]
+  fn test_orphans() {
+    let root = tree_fixture(
+      "orphans",
+      &[
+        ("a.md", "[[b]] [[a]]"),
+        ("b.md", ""),
+        ("c.md", "[self](c.md)"),
+        ("image.png", ""),
+        // plain text notes link and get linked alike
+        ("d.txt", "[[e]]"),
+        ("e.txt", ""),
+      ],
+    );
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, true, &WalkOptions::default())
+        .unwrap();
+    let id = |rel: &str| tree.find_by_path(&root.join(rel)).unwrap();
+
+    assert_eq!(tree.orphans(), vec![id("a.md"), id("c.md"), id("d.txt")]);
+  }
+
+  #[test]
+  fn test_broken_links() {
+    let root = tree_fixture(
+      "broken_links",
+      &[
+        (
+          "a.md",
+          "[[b]] [[missing]] [ok](sub/c.md) [dead](sub/gone.md) [web](https://x.y) [top](#top)",
+        ),
+        ("b.md", ""),
+        ("sub/c.md", "[up](../b.md) [[a]]"),
+      ],
+    );
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, true, &WalkOptions::default())
+        .unwrap();
+    let a = canonicalize(root.join("a.md")).unwrap();
+
+    assert_eq!(
+      tree.broken_links(),
+      vec![
+        (a.clone(), "missing".to_string()),
+        (a, "sub/gone.md".to_string())
+      ]
+    );
+  }
+
+  #[test]
+  fn test_backlink_counts() {
+    let root = tree_fixture(
+      "backlink_counts",
+      &[
+        ("hub.md", "[[hub]] [[a]]"),
+        ("a.md", "[[hub]] [again](hub.md)"),
+        ("b.md", "[[Hub]]"),
+        ("sub/c.md", "[hub](../hub.md)"),
+      ],
+    );
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, true, &WalkOptions::default())
+        .unwrap();
+    let root = canonicalize(&root).unwrap();
+
+    let counts = tree.backlink_counts();
+    assert_eq!(counts.len(), 2);
+    assert_eq!(counts[&root.join("hub.md")], 3);
+    assert_eq!(counts[&root.join("a.md")], 1);
+  }
+
+  #[test]
+  fn test_recently_modified() {
+    use std::time::{Duration, SystemTime};
+
+    let root = tree_fixture(
+      "recently_modified",
+      &[
+        ("old.md", ""),
+        ("older.md", ""),
+        ("fresh.md", ""),
+        ("sub/", ""),
+      ],
+    );
+    let day = Duration::from_secs(24 * 60 * 60);
+    for (name, days) in [("old.md", 3), ("older.md", 10)] {
+      fs::File::options()
+        .write(true)
+        .open(root.join(name))
+        .unwrap()
+        .set_modified(SystemTime::now() - day * days)
+        .unwrap();
+    }
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+    let id = |rel: &str| tree.find_by_path(&root.join(rel)).unwrap();
+
+    assert_eq!(tree.recently_modified(day), vec![id("fresh.md")]);
+    assert_eq!(
+      tree.recently_modified(day * 7),
+      vec![id("fresh.md"), id("old.md")]
+    );
+  }
+
+  #[test]
+  fn test_extension_stats() {
+    let root = tree_fixture(
+      "extension_stats",
+      &[
+        ("a.md", "1234"),
+        ("sub/b.MD", "12"),
+        ("c.png", "123"),
+        ("Makefile", "1"),
+        (".hidden/", ""),
+      ],
+    );
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+
+    let stats = tree.extension_stats();
+    assert_eq!(stats.len(), 3);
+    assert_eq!(stats["md"], (2, 6));
+    assert_eq!(stats["png"], (1, 3));
+    assert_eq!(stats[NO_EXTENSION], (1, 1));
+  }
+
+  #[test]
+  fn test_largest_files() {
+    let root = tree_fixture(
+      "largest_files",
+      &[
+        ("small.md", "1"),
+        ("big.md", "12345"),
+        ("sub/mid.md", "123"),
+        ("sub/tie.md", "123"),
+      ],
+    );
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+    let id = |rel: &str| tree.find_by_path(&root.join(rel)).unwrap();
+
+    assert_eq!(tree.largest_files(2), vec![id("big.md"), id("sub/mid.md")]);
+    assert_eq!(tree.largest_files(10).len(), 4);
+    assert!(tree.largest_files(0).is_empty());
+  }
+
+  #[test]
+  fn test_node_reading_time() {
+    let words = vec!["word"; 500].join(" ");
+    let text = format!("---\ntitle: Skip Me\n---\n{words}\n```\nlet x = 1;\n```\n");
+    let root = tree_fixture("reading_time", &[("note.md", &text)]);
+    let reading_time = |opts: &WalkOptions, read_ctn| {
+      let tree = Tree::init(root.to_str().unwrap(), None, read_ctn, opts).unwrap();
+      let note = tree.children_vec().pop().unwrap();
+      note.reading_time_secs
+    };
+
+    // 500 words at 200 wpm: 2.5 minutes
+    assert_eq!(reading_time(&WalkOptions::default(), true), Some(150));
+    let slow = WalkOptions {
+      words_per_minute: 100,
+      ..Default::default()
+    };
+    assert_eq!(reading_time(&slow, true), Some(300));
+    assert_eq!(reading_time(&WalkOptions::default(), false), None);
+  }
+
+  #[test]
+  fn test_tree_code_language_stats() {
+    let rust = "```rust\nfn main() {}\n```\n\n```Rust ignore\nlet x = 1;\n```\n";
+    let python = "~~~python\nprint(1)\n~~~\n```\nplain\n```\n\n    indented\n";
+    let root = tree_fixture(
+      "code_language_stats",
+      &[("a.md", rust), ("b.md", python), ("c.md", "no code")],
+    );
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, true, &WalkOptions::default())
+        .unwrap();
+
+    let stats = tree.code_language_stats();
+    assert_eq!(stats.len(), 3);
+    assert_eq!(stats["rust"], 2);
+    assert_eq!(stats["python"], 1);
+    assert_eq!(stats[NO_LANGUAGE], 1);
+
+    let unread =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+    assert!(unread.code_language_stats().is_empty());
+  }
+
+  #[test]
+  fn test_node_headings() {
+    let text = "---\ntitle: Outline\n---\n# Intro ##\n\nSome text.\n\n\
+      ```\n# not a heading\n```\n## Details\n\nSetext Title\n===\n\n\
+      ---\n#hashtag\n";
+    let root = tree_fixture("headings", &[("note.md", text)]);
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, true, &WalkOptions::default())
+        .unwrap();
+
+    let note = tree.children_vec().pop().unwrap();
+    let outline: Vec<(u8, &str, usize)> = note
+      .headings
+      .iter()
+      .map(|h| (h.level, h.text.as_str(), h.line))
+      .collect();
+    assert_eq!(
+      outline,
+      vec![(1, "Intro", 4), (2, "Details", 11), (1, "Setext Title", 13)]
+    );
+  }
+
+  #[test]
+  fn test_tree_builder() {
+    let root = tree_fixture(
+      "builder",
+      &[
+        ("a.md", "# A"),
+        ("b.txt", "b"),
+        (".hidden.md", "hidden"),
+        ("sub/", ""),
+        ("sub/c.md", "# C"),
+        ("sub/deep/", ""),
+        ("sub/deep/d.md", "# D"),
+      ],
+    );
+    let dir = root.to_str().unwrap();
+    let paths = |tree: &Tree| {
+      let mut paths: Vec<(PathBuf, Option<String>)> = tree
+        .iter()
+        .map(|node| (node.path().to_owned(), node.file_text.clone()))
+        .collect();
+      paths.sort();
+      paths
+    };
+
+    let default = Tree::builder().build(dir).unwrap();
+    let init = Tree::init(dir, None, false, &WalkOptions::default()).unwrap();
+    assert_eq!(paths(&default), paths(&init));
+
+    let built = Tree::builder()
+      .depth(1)
+      .read_content(true)
+      .show_hidden(true)
+      .threads(2)
+      .include_globs(["*.md"])
+      .build(dir)
+      .unwrap();
+    let opts = WalkOptions {
+      show_hidden: true,
+      threads: Some(2),
+      include_globs: vec!["*.md".to_string()],
+      ..Default::default()
+    };
+    let init = Tree::init(dir, Some(1), true, &opts).unwrap();
+    assert_eq!(paths(&built), paths(&init));
+    assert!(built.iter().any(|node| node.file_name() == ".hidden.md"));
+    assert!(built.iter().all(|node| node.file_name() != "b.txt"));
+  }
+
+  #[test]
+  fn test_tree_change_payload() {
+    let root = tree_fixture("change_payload", &[("note.md", "# Note")]);
+    let path = canonicalize(root.join("note.md")).unwrap();
+    let node = Node::from_path(&path, 1, &ReadMode::None.into()).unwrap();
+
+    let payload =
+      |event: &TreeEvent| serde_json::to_value(TreeChange::from(event)).unwrap();
+    let expected = |kind: &str| serde_json::json!({ "kind": kind, "path": path.to_str().unwrap() });
+    assert_eq!(payload(&TreeEvent::Created(node)), expected("created"));
+    assert_eq!(
+      payload(&TreeEvent::Removed(path.clone())),
+      expected("removed")
+    );
+    assert_eq!(
+      payload(&TreeEvent::Modified(path.clone())),
+      expected("modified")
+    );
+  }
+
+  #[test]
+  fn test_watch_debouncer() {
+    use std::time::Duration;
+
+    let mut debouncer = Debouncer::new(Duration::from_millis(200));
+    assert_eq!(debouncer.remaining(), None);
+
+    let (a, b, c, d) = (
+      PathBuf::from("a.md"),
+      PathBuf::from("b.md"),
+      PathBuf::from("c.md"),
+      PathBuf::from("d.md"),
+    );
+    debouncer.push(a.clone(), ChangeKind::Created);
+    debouncer.push(a.clone(), ChangeKind::Modified);
+    // save through a rename
+    debouncer.push(b.clone(), ChangeKind::Removed);
+    debouncer.push(b.clone(), ChangeKind::Created);
+    debouncer.push(b.clone(), ChangeKind::Modified);
+    // temporary file
+    debouncer.push(c.clone(), ChangeKind::Created);
+    debouncer.push(c.clone(), ChangeKind::Removed);
+    debouncer.push(d.clone(), ChangeKind::Modified);
+    debouncer.push(d.clone(), ChangeKind::Removed);
+    assert!(debouncer.remaining().unwrap() <= Duration::from_millis(200));
+
+    assert_eq!(
+      debouncer.drain(),
+      vec![
+        (a, ChangeKind::Created),
+        (b, ChangeKind::Modified),
+        (d, ChangeKind::Removed)
+      ]
+    );
+    assert_eq!(debouncer.remaining(), None);
+  }
+
+  #[test]
+  fn test_tree_reindex_path() {
+    let root = tree_fixture(
+      "reindex_path",
+      &[("a.md", "# A\n#old"), ("b.md", "# B\n#old [[a]]")],
+    );
+    let mut tree =
+      Tree::init(root.to_str().unwrap(), None, true, &WalkOptions::default())
+        .unwrap();
+    let a = canonicalize(root.join("a.md")).unwrap();
+    let b = canonicalize(root.join("b.md")).unwrap();
+    let a_id = tree.find_by_path(&a).unwrap();
+
+    fs::write(&a, "# New Title\n#new #fresh words [[b]]").unwrap();
+    tree.reindex_path(&a).unwrap();
+    assert_eq!(tree.find_by_path(&a), Some(a_id));
+    let node = tree.inner()[a_id].get();
+    assert_eq!(node.title(), "New Title");
+    assert_eq!(node.links, vec!["b"]);
+    assert_eq!(node.word_count, 6);
+
+    let index = tree.tag_index();
+    assert_eq!(index["old"], vec![b.clone()]);
+    assert_eq!(index["new"], vec![a.clone()]);
+    assert_eq!(index["fresh"], vec![a.clone()]);
+
+    // deleted before the reindex
+    fs::remove_file(&b).unwrap();
+    tree.reindex_path(&b).unwrap();
+    assert_eq!(tree.find_by_path(&b), None);
+    assert!(!tree.tag_index().contains_key("old"));
+  }
+
+  #[test]
+  fn test_node_sniff_kind() {
+    use std::collections::HashMap;
+
+    let png: &[u8] = &[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n', 0, 0];
+    let root = tree_fixture(
+      "sniff_kind",
+      &[
+        ("note", "---\ntitle: Note\n---\nbody"),
+        ("plain", "just text"),
+      ],
+    );
+    fs::write(root.join("picture"), png).unwrap();
+    fs::write(root.join("picture.txt"), png).unwrap();
+    fs::write(root.join("named.png"), "not an image").unwrap();
+
+    let kinds = |sniff_kind| {
+      let opts = WalkOptions {
+        sniff_kind,
+        ..Default::default()
+      };
+      let tree = Tree::init(root.to_str().unwrap(), None, false, &opts).unwrap();
+      tree
+        .iter()
+        .map(|node| (node.file_name_lossy().to_string(), node.kind()))
+        .collect::<HashMap<_, _>>()
+    };
+
+    let sniffed = kinds(true);
+    assert_eq!(sniffed["picture"], FileKind::Image);
+    assert_eq!(sniffed["picture.txt"], FileKind::Image);
+    assert_eq!(sniffed["note"], FileKind::Markdown);
+    assert_eq!(sniffed["plain"], FileKind::Text);
+    // the extension comes first
+    assert_eq!(sniffed["named.png"], FileKind::Image);
+
+    let plain = kinds(false);
+    assert_eq!(plain["picture"], FileKind::Other);
+    assert_eq!(plain["picture.txt"], FileKind::Text);
+  }
+
+  #[test]
+  fn test_tree_refresh_subtree() {
+    let root = tree_fixture(
+      "refresh_subtree",
+      &[
+        ("a.md", "a"),
+        ("projects/", ""),
+        ("projects/old.md", "old"),
+        ("projects/keep.md", "keep"),
+        ("other/", ""),
+        ("other/b.md", "b"),
+      ],
+    );
+    let mut tree =
+      Tree::init(root.to_str().unwrap(), None, true, &WalkOptions::default())
+        .unwrap();
+    let projects = canonicalize(root.join("projects")).unwrap();
+    let projects_id = tree.find_by_path(&projects).unwrap();
+    let other_b = canonicalize(root.join("other/b.md")).unwrap();
+    let other_b_id = tree.find_by_path(&other_b).unwrap();
+
+    fs::remove_file(root.join("projects/old.md")).unwrap();
+    fs::write(root.join("projects/keep.md"), "changed").unwrap();
+    fs::create_dir(root.join("projects/sub")).unwrap();
+    fs::write(root.join("projects/sub/new.md"), "new").unwrap();
+    // outside of the refreshed subtree
+    fs::write(root.join("other/b.md"), "b changed").unwrap();
+    fs::write(root.join("other/c.md"), "c").unwrap();
+
+    tree.refresh_subtree(projects_id).unwrap();
+    assert_eq!(tree.find_by_path(&projects), Some(projects_id));
+    assert_eq!(tree.find_by_path(&projects.join("old.md")), None);
+    let keep = tree.find_by_path(&projects.join("keep.md")).unwrap();
+    assert_eq!(
+      tree.inner()[keep].get().file_text.as_deref(),
+      Some("changed")
+    );
+    let new = tree.find_by_path(&projects.join("sub/new.md")).unwrap();
+    assert_eq!(tree.inner()[new].get().depth, 3);
+    assert!(projects_id.descendants(tree.inner()).any(|id| id == new));
+
+    assert_eq!(tree.find_by_path(&other_b), Some(other_b_id));
+    let b = tree.inner()[other_b_id].get();
+    assert_eq!(b.file_text.as_deref(), Some("b"));
+    assert_eq!(tree.find_by_path(&root.join("other/c.md")), None);
+
+    let file = tree.find_by_path(&canonicalize(root.join("a.md")).unwrap());
+    assert!(tree.refresh_subtree(file.unwrap()).is_err());
+  }
+
+  #[test]
+  fn test_tree_traversal_stats() {
+    let root = tree_fixture(
+      "traversal_stats",
+      &[
+        ("a.md", "12345"),
+        ("b.txt", "123"),
+        ("sub/", ""),
+        ("sub/c.md", "1234567890"),
+        ("sub/deep/", ""),
+      ],
+    );
+    let opts = WalkOptions {
+      threads: Some(1),
+      ..Default::default()
+    };
+
+    let tree = Tree::init(root.to_str().unwrap(), None, true, &opts).unwrap();
+    let stats = tree.traversal_stats().unwrap();
+    assert_eq!((stats.files, stats.dirs), (3, 3));
+    // markdown only
+    assert_eq!(stats.bytes_read, 15);
+    assert_eq!(stats.threads_used, 1);
+
+    let unread = Tree::init(root.to_str().unwrap(), None, false, &opts).unwrap();
+    let stats = unread.traversal_stats().unwrap();
+    assert_eq!((stats.files, stats.dirs, stats.bytes_read), (3, 3, 0));
+
+    let built = Tree::new(tree.inner().clone(), tree.root);
+    assert_eq!(built.traversal_stats(), None);
+  }
+
+  #[cfg(feature = "parallel")]
+  #[test]
+  fn test_search_content_par() {
+    use crate::tree::search::SearchHit;
+
+    let root = tree_fixture(
+      "search_content_par",
+      &[
+        ("a.md", "needle\nhay\nneedle again"),
+        ("b.md", "hay"),
+        ("sub/c.md", "NEEDLE"),
+        ("sub/deep/d.md", "hay\nhay\nthe needle"),
+      ],
+    );
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, true, &WalkOptions::default())
+        .unwrap();
+    let key =
+      |hit: &SearchHit| (tree.inner()[hit.node].get().path().to_owned(), hit.line);
+
+    for case_insensitive in [false, true] {
+      let mut serial = tree.search_content("needle", case_insensitive);
+      serial.sort_by_key(key);
+      let parallel = tree.search_content_par("needle", case_insensitive);
+      assert_eq!(parallel, serial);
+    }
+    assert_eq!(tree.search_content_par("needle", true).len(), 4);
+  }
+
+  #[cfg(any(target_os = "macos", target_os = "windows"))]
+  #[test]
+  fn test_tree_find_by_path_case_insensitive() {
+    let root = tree_fixture("find_by_path_case", &[("sub/foo.md", "foo")]);
+    let mut tree =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+    let foo = tree.find_by_path(&root.join("sub/foo.md")).unwrap();
+
+    assert_eq!(tree.find_by_path(&root.join("SUB/FOO.MD")), Some(foo));
+    // the same file, not a second node
+    assert_eq!(tree.insert_path(&root.join("sub/Foo.md")).unwrap(), foo);
+    tree.remove_path(&root.join("Sub/foo.MD")).unwrap();
+    assert_eq!(tree.find_by_path(&root.join("sub/foo.md")), None);
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn test_tree_max_symlink_depth() {
+    use std::os::unix::fs::symlink;
+
+    let root = tree_fixture("max_symlink_depth", &[("note.md", "note")]);
+    let external = tree_fixture(
+      "max_symlink_depth_ext",
+      &[("one/a.md", "a"), ("two/b.md", "b"), ("three/c.md", "c")],
+    );
+    symlink(external.join("one"), root.join("l1")).unwrap();
+    symlink(external.join("two"), external.join("one/l2")).unwrap();
+    symlink(external.join("three"), external.join("two/l3")).unwrap();
+
+    let names = |max_symlink_depth| {
+      let opts = WalkOptions {
+        follow_links: true,
+        max_symlink_depth,
+        ..WalkOptions::default()
+      };
+      let tree = Tree::init(root.to_str().unwrap(), None, false, &opts).unwrap();
+      let mut names: Vec<String> = tree
+        .iter()
+        .map(|n| n.file_name_lossy().to_string())
+        .filter(|name| name.ends_with(".md"))
+        .collect();
+      names.sort();
+      names
+    };
+
+    // `l3` is listed, but not entered
+    assert_eq!(names(2), vec!["a.md", "b.md", "note.md"]);
+    assert_eq!(names(usize::MAX), vec!["a.md", "b.md", "c.md", "note.md"]);
+    assert_eq!(names(0), vec!["note.md"]);
+  }
+
+  #[test]
+  fn test_walk_options_serde() {
+    let opts = WalkOptions {
+      show_hidden: true,
+      custom_ignore_files: vec![".mdignore".to_string()],
+      threads: Some(2),
+      encoding: Encoding::Other(encoding_rs::SHIFT_JIS),
+      ..WalkOptions::default()
+    };
+    let json = serde_json::to_value(&opts).unwrap();
+    assert_eq!(
+      json["encoding"],
+      serde_json::json!({ "Other": "Shift_JIS" })
+    );
+    let back: WalkOptions = serde_json::from_value(json).unwrap();
+    assert_eq!(back, opts);
+
+    // missing fields take their default
+    let partial: WalkOptions =
+      serde_json::from_str(r#"{ "show_hidden": true }"#).unwrap();
+    let expected = WalkOptions {
+      show_hidden: true,
+      ..WalkOptions::default()
+    };
+    assert_eq!(partial, expected);
+    assert!(serde_json::from_str::<WalkOptions>(
+      r#"{ "encoding": { "Other": "no-such-encoding" } }"#
+    )
+    .is_err());
+  }
+
+  #[test]
+  fn test_walk_options_default() {
+    let root = tree_fixture(
+      "walk_options_default",
+      &[
+        ("a.md", "a"),
+        (".hidden.md", "hidden"),
+        (".ignore", "ignored.md"),
+        ("ignored.md", "ignored"),
+        (".gitignore", "gitignored.md"),
+        ("gitignored.md", "kept, no git repo"),
+        ("sub/", ""),
+        ("sub/b.txt", "b"),
+      ],
+    );
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+    let mut names: Vec<String> = tree
+      .iter()
+      .skip(1)
+      .map(|n| n.file_name_lossy().to_string())
+      .collect();
+    names.sort();
+    assert_eq!(names, vec!["a.md", "b.txt", "gitignored.md", "sub"]);
+  }
+
+  #[test]
+  fn test_node_icon() {
+    use std::collections::HashMap;
+
+    let root = tree_fixture(
+      "node_icon",
+      &[
+        ("note.md", "# Note"),
+        ("photo.png", ""),
+        ("data.csv", ""),
+        ("sub/", ""),
+      ],
+    );
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+    let icon = |name: &str| {
+      let path = canonicalize(root.join(name)).unwrap();
+      let id = tree.find_by_path(&path).unwrap();
+      tree.inner()[id].get().icon()
+    };
+    assert_eq!(icon("note.md"), "📝");
+    assert_eq!(icon("sub"), "📁");
+    assert_eq!(icon("photo.png"), "🖼");
+    assert_eq!(icon("data.csv"), "📄");
+
+    let overrides = HashMap::from([(FileKind::Markdown, "M".to_string())]);
+    assert_eq!(FileKind::Markdown.icon_with(&overrides), "M");
+    assert_eq!(FileKind::Directory.icon_with(&overrides), "📁");
+  }
+
+  #[test]
+  fn test_tree_max_depth() {
+    let root = tree_fixture("max_depth", &[("a/b/c/d.md", "d"), ("e.md", "e")]);
+    let tree =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+    assert_eq!(tree.max_depth(), 4);
+
+    let empty = tree_fixture("max_depth_empty", &[]);
+    let tree = Tree::init(
+      empty.to_str().unwrap(),
+      None,
+      false,
+      &WalkOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(tree.max_depth(), 0);
+  }
+
+  #[test]
+  fn test_tree_direct_counts() {
+    let root = tree_fixture(
+      "direct_counts",
+      &[
+        ("dir/a.md", "a"),
+        ("dir/b.md", "b"),
+        ("dir/c.txt", "c"),
+        ("dir/one/", ""),
+        ("dir/two/", ""),
+        ("dir/two/deep.md", "deep"),
+      ],
+    );
+    let mut tree =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+    let dir = canonicalize(root.join("dir")).unwrap();
+    let dir_id = tree.find_by_path(&dir).unwrap();
+    let counts = |tree: &Tree| {
+      let node = tree.inner()[dir_id].get();
+      (node.direct_child_count, node.direct_file_count)
+    };
+    assert_eq!(counts(&tree), (5, 3));
+    let root_node = tree.inner()[tree.root].get();
+    assert_eq!(
+      (root_node.direct_child_count, root_node.direct_file_count),
+      (1, 0)
+    );
+
+    fs::write(dir.join("d.md"), "d").unwrap();
+    tree.insert_path(&dir.join("d.md")).unwrap();
+    assert_eq!(counts(&tree), (6, 4));
+    tree.remove_path(&dir.join("one")).unwrap();
+    assert_eq!(counts(&tree), (5, 4));
+  }
+
+  #[test]
+  fn test_tree_read_if() {
+    let root = tree_fixture(
+      "read_if",
+      &[
+        ("journal/day.md", "# Day"),
+        ("journal/empty.md", ""),
+        ("notes/other.md", "# Other"),
+        ("top.md", "# Top"),
+      ],
+    );
+    let journal = canonicalize(root.join("journal")).unwrap();
+    let tree = Tree::builder()
+      .read_content_if(move |path| path.starts_with(&journal))
+      .build(root.to_str().unwrap())
+      .unwrap();
+    let text = |name: &str| {
+      let id = tree.find_by_path(&root.join(name)).unwrap();
+      tree.inner()[id].get().file_text.clone()
+    };
+    assert_eq!(text("journal/day.md").as_deref(), Some("# Day"));
+    assert_eq!(text("notes/other.md"), None);
+    assert_eq!(text("top.md"), None);
+
+    // non-empty markdown only
+    let opts = WalkOptions {
+      read_if: Some(ReadPredicate::new(|path| {
+        fs::metadata(path).is_ok_and(|meta| meta.len() > 0)
+      })),
+      markdown_only: true,
+      ..WalkOptions::default()
+    };
+    let tree = Tree::init(root.to_str().unwrap(), None, true, &opts).unwrap();
+    let mut read: Vec<String> = tree
+      .iter()
+      .filter(|node| node.file_text.is_some())
+      .map(|node| node.file_name_lossy().to_string())
+      .collect();
+    read.sort();
+    assert_eq!(read, vec!["day.md", "other.md", "top.md"]);
+  }
+
+  #[test]
+  fn test_tree_sort_natural() {
+    let root = tree_fixture(
+      "sort_natural",
+      &[
+        ("Chapter 2.md", ""),
+        ("chapter 10.md", ""),
+        ("Chapter 1.md", ""),
+        ("Chapter 02.md", ""),
+        ("appendix.md", ""),
+      ],
+    );
+    let mut tree =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+    let names = |tree: &Tree| -> Vec<String> {
+      tree
+        .children_vec()
+        .iter()
+        .map(|n| n.file_name_lossy().to_string())
+        .collect()
+    };
+
+    let opts = SortOptions {
+      natural: true,
+      ..SortOptions::default()
+    };
+    tree.sort_children_with(SortKey::Name, &opts).unwrap();
+    assert_eq!(
+      names(&tree),
+      vec![
+        "appendix.md",
+        "Chapter 1.md",
+        "Chapter 2.md",
+        "Chapter 02.md",
+        "chapter 10.md"
+      ]
+    );
+    tree.sort_children(SortKey::Name, true).unwrap();
+    assert_eq!(names(&tree)[1..3], ["Chapter 02.md", "Chapter 1.md"]);
+  }
+
+  #[test]
+  fn test_tree_empty_files() {
+    let root = tree_fixture(
+      "empty_files",
+      &[
+        ("empty.md", ""),
+        ("space.md", " "),
+        ("blank.md", "\n\t\n"),
+        ("note.md", "# Note"),
+        ("empty.txt", ""),
+        ("space.txt", " "),
+        ("sub/", ""),
+      ],
+    );
+    let names = |tree: &Tree, whitespace_only| {
+      let mut names: Vec<String> = tree
+        .empty_files(whitespace_only)
+        .into_iter()
+        .map(|id| tree.inner()[id].get().file_name_lossy().to_string())
+        .collect();
+      names.sort();
+      names
+    };
+
+    let read =
+      Tree::init(root.to_str().unwrap(), None, true, &WalkOptions::default())
+        .unwrap();
+    assert_eq!(names(&read, false), vec!["empty.md", "empty.txt"]);
+    assert_eq!(
+      names(&read, true),
+      vec!["blank.md", "empty.md", "empty.txt", "space.md"]
+    );
+
+    // whitespace needs the content
+    let unread =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+    assert_eq!(names(&unread, true), vec!["empty.md", "empty.txt"]);
+  }
+
+  #[test]
+  fn test_node_modified_ago() {
+    use std::time::{Duration, SystemTime};
+
+    let root = tree_fixture("modified_ago", &[("note.md", "note")]);
+    let path = canonicalize(root.join("note.md")).unwrap();
+    let node = Node::from_path(&path, 1, &ReadMode::None.into()).unwrap();
+    let modified = node.modified().unwrap();
+    let ago = |secs| node.modified_ago(modified + Duration::from_secs(secs));
+
+    assert_eq!(ago(0).as_deref(), Some("just now"));
+    assert_eq!(ago(59).as_deref(), Some("just now"));
+    assert_eq!(ago(60).as_deref(), Some("1 minute ago"));
+    assert_eq!(ago(5 * 60).as_deref(), Some("5 minutes ago"));
+    assert_eq!(ago(90 * 60).as_deref(), Some("1 hour ago"));
+    assert_eq!(ago(3 * 24 * 3600).as_deref(), Some("3 days ago"));
+    assert_eq!(ago(8 * 24 * 3600).as_deref(), Some("1 week ago"));
+    assert_eq!(ago(400 * 24 * 3600).as_deref(), Some("1 year ago"));
+    let skewed = node.modified_ago(modified - Duration::from_secs(10));
+    assert_eq!(skewed.as_deref(), Some("just now"));
+
+    // built without metadata
+    let bare = Node::new(1, "note.md".into(), None, None, None, path);
+    assert_eq!(bare.modified_ago(SystemTime::now()), None);
+  }
+
+  #[test]
+  fn test_to_html() {
+    let root = tree_fixture(
+      "html",
+      &[
+        ("a/b.md", "b"),
+        ("a/Q&A's draft.md", "q"),
+        ("e/", ""),
+        ("f.md", "f"),
+      ],
+    );
+    let mut tree =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+    tree.sort_children(SortKey::Name, true).unwrap();
+
+    let expected = "\
+<ul>
+  <li>html
+    <ul>
+      <li>a
+        <ul>
+          <li><a href=\"a/b.md\">b.md</a></li>
+          <li><a href=\"a/Q%26A%27s%20draft.md\">Q&amp;A&#39;s draft.md</a></li>
+        </ul>
+      </li>
+      <li>e</li>
+      <li><a href=\"f.md\">f.md</a></li>
+    </ul>
+  </li>
+</ul>
+";
+    assert_eq!(tree.to_html(), expected);
+  }
+
+  #[test]
+  fn test_tree_merge() {
+    let root = tree_fixture(
+      "merge",
+      &[
+        ("a/one.md", "one"),
+        ("a/keep.md", "keep"),
+        ("b/", ""),
+        ("top.md", "t"),
+      ],
+    );
+    let mut tree =
+      Tree::init(root.to_str().unwrap(), None, true, &WalkOptions::default())
+        .unwrap();
+    let a = canonicalize(root.join("a")).unwrap();
+    let a_id = tree.find_by_path(&a).unwrap();
+
+    fs::write(a.join("one.md"), "one changed").unwrap();
+    fs::write(a.join("two.md"), "two").unwrap();
+    fs::create_dir(a.join("sub")).unwrap();
+    fs::write(a.join("sub/deep.md"), "deep").unwrap();
+    let other =
+      Tree::init(a.to_str().unwrap(), None, true, &WalkOptions::default()).unwrap();
+
+    let overwritten = tree.merge(other);
+    assert_eq!(overwritten, vec![a.join("one.md")]);
+    assert_eq!(tree.find_by_path(&a), Some(a_id));
+    let text = |tree: &Tree, path: &Path| {
+      let id = tree.find_by_path(path).unwrap();
+      tree.inner()[id].get().file_text.clone().unwrap()
+    };
+    assert_eq!(text(&tree, &a.join("one.md")), "one changed");
+    assert_eq!(text(&tree, &a.join("keep.md")), "keep");
+    let deep = tree.find_by_path(&a.join("sub/deep.md")).unwrap();
+    assert_eq!(tree.inner()[deep].get().depth, 3);
+    let mut names: Vec<String> = a_id
+      .children(tree.inner())
+      .map(|id| tree.inner()[id].get().file_name_lossy().to_string())
+      .collect();
+    names.sort();
+    assert_eq!(names, vec!["keep.md", "one.md", "sub", "two.md"]);
+    assert_eq!(tree.inner()[a_id].get().direct_child_count, 4);
+
+    // a directory new to the tree goes below its parent
+    fs::create_dir(root.join("c")).unwrap();
+    fs::write(root.join("c/new.md"), "new").unwrap();
+    let c = canonicalize(root.join("c")).unwrap();
+    let other =
+      Tree::init(c.to_str().unwrap(), None, true, &WalkOptions::default()).unwrap();
+    assert!(tree.merge(other).is_empty());
+    let c_id = tree.find_by_path(&c).unwrap();
+    assert_eq!(tree.inner()[c_id].parent(), Some(tree.root));
+    assert_eq!(tree.inner()[c_id].get().depth, 1);
+    assert_eq!(text(&tree, &c.join("new.md")), "new");
+  }
+
+  #[test]
+  fn test_max_content_bytes() {
+    let big = "x".repeat(2048);
+    let root = tree_fixture(
+      "max_content_bytes",
+      &[("big.md", big.as_str()), ("small.md", "# small")],
+    );
+    let opts = WalkOptions {
+      max_content_bytes: Some(1024),
+      ..WalkOptions::default()
+    };
+    let tree = Tree::init(root.to_str().unwrap(), None, true, &opts).unwrap();
+    let root = canonicalize(&root).unwrap();
+
+    let big_id = tree.find_by_path(&root.join("big.md")).unwrap();
+    let big = tree.inner()[big_id].get();
+    assert!(big.content_skipped);
+    assert_eq!(big.file_text, None);
+    assert_eq!(big.content_hash, None);
+    assert_eq!(big.size, 2048);
+
+    let small_id = tree.find_by_path(&root.join("small.md")).unwrap();
+    let small = tree.inner()[small_id].get();
+    assert!(!small.content_skipped);
+    assert_eq!(small.file_text.as_deref(), Some("# small"));
+  }
+
+  #[test]
+  fn test_cache_sort_expand() {
+    let root = tree_fixture(
+      "cache_sort_expand",
+      &[
+        ("a.md", "aaa"),
+        ("b.md", "b"),
+        ("big/c.md", ""),
+        ("big/d.txt", ""),
+        ("big/.hidden.md", ""),
+      ],
+    );
+    let opts = WalkOptions {
+      lazy_dirs: true,
+      exclude_globs: vec!["big/*.txt".to_string()],
+      ..WalkOptions::default()
+    };
+    let tree = Tree::init(root.to_str().unwrap(), None, false, &opts).unwrap();
+    let cache = root.with_extension("json");
+    tree.save_cache(&cache).unwrap();
+    let mut loaded = Tree::load_cache(&cache).unwrap();
+    fs::remove_file(&cache).unwrap();
+
+    loaded.sort_children(SortKey::Size, false).unwrap();
+    let names: Vec<String> = loaded
+      .children_vec()
+      .iter()
+      .map(|n| n.file_name_lossy().to_string())
+      .collect();
+    assert_eq!(names, vec!["a.md", "b.md", "big"]);
+
+    let big = loaded.find_by_path(&root.join("big")).unwrap();
+    let names: Vec<String> = loaded
+      .expand(big)
+      .unwrap()
+      .iter()
+      .map(|id| loaded.inner()[*id].get().file_name_lossy().to_string())
+      .collect();
+    // excluded and hidden entries stay out
+    assert_eq!(names, vec!["c.md"]);
+  }
+
+  #[test]
+  fn test_tree_merge_root() {
+    let root = tree_fixture("merge_root", &[("a.md", "a"), ("note", "n")]);
+    let root = canonicalize(&root).unwrap();
+    let mut tree =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+    let root_id = tree.root;
+
+    fs::write(root.join("b.md"), "b").unwrap();
+    let other =
+      Tree::init(root.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+    assert!(tree.merge(other).is_empty());
+    assert_eq!(tree.root, root_id);
+    assert_eq!(tree.children_vec().len(), 3);
+
+    // a file root turned directory
+    let note = root.join("note");
+    let mut tree =
+      Tree::init(note.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+    fs::remove_file(&note).unwrap();
+    fs::create_dir(&note).unwrap();
+    fs::write(note.join("c.md"), "c").unwrap();
+    let other =
+      Tree::init(note.to_str().unwrap(), None, false, &WalkOptions::default())
+        .unwrap();
+    assert_eq!(tree.merge(other), vec![note.clone()]);
+    assert!(tree.inner()[tree.root].get().is_dir());
+    assert!(tree.find_by_path(&note.join("c.md")).is_some());
+  }
+
+  #[test]
+  fn test_tree_watch_removed_dir() {
+    let root = tree_fixture("watch_removed_dir", &[("sub/a.md", "a")]);
+    let opts = WalkOptions {
+      markdown_only: true,
+      ..WalkOptions::default()
+    };
+    let (tx, rx) = std::sync::mpsc::channel();
+    let handle = Tree::watch(root.to_str().unwrap(), false, &opts, move |e| {
+      tx.send(e).unwrap_or(());
+    })
+    .unwrap();
+
+    fs::remove_dir_all(root.join("sub")).unwrap();
+    let sub = canonicalize(&root).unwrap().join("sub");
+    let removed =
+      std::iter::from_fn(|| rx.recv_timeout(std::time::Duration::from_secs(5)).ok())
+        .any(|event| matches!(event, TreeEvent::Removed(path) if path == sub));
+    assert!(removed);
+    drop(handle);
+  }
+
+  #[test]
+  fn test_tree_watch_nested_ignore() {
+    let root = tree_fixture(
+      "watch_nested_ignore",
+      &[("sub/.gitignore", "skip.md\n"), ("sub/a.md", "a")],
+    );
+    let opts = WalkOptions {
+      git_ignore: true,
+      ..WalkOptions::default()
+    };
+    let (tx, rx) = std::sync::mpsc::channel();
+    let handle = Tree::watch(root.to_str().unwrap(), false, &opts, move |e| {
+      tx.send(e).unwrap_or(());
+    })
+    .unwrap();
+
+    fs::write(root.join("sub/skip.md"), "s").unwrap();
+    fs::write(root.join("sub/keep.md"), "k").unwrap();
+    let mut names = Vec::new();
+    while let Ok(event) = rx.recv_timeout(std::time::Duration::from_secs(5)) {
+      if let TreeEvent::Created(node) = event {
+        names.push(node.file_name_lossy().to_string());
+        if names.iter().any(|name| name == "keep.md") {
+          break;
+        }
+      }
+    }
+    assert_eq!(names, vec!["keep.md"]);
+    drop(handle);
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn test_refresh_followed_link() {
+    let root = tree_fixture("refresh_link", &[("a.md", "a")]);
+    let target = tree_fixture("refresh_link_target", &[("y.md", "y")]);
+    std::os::unix::fs::symlink(&target, root.join("dir_link")).unwrap();
+    let opts = WalkOptions {
+      follow_links: true,
+      lazy_dirs: true,
+      ..WalkOptions::default()
+    };
+    let mut tree = Tree::init(root.to_str().unwrap(), None, false, &opts).unwrap();
+    let link = canonicalize(&root).unwrap().join("dir_link");
+    let link_id = tree.find_by_path(&link).unwrap();
+
+    tree.expand(link_id).unwrap();
+    assert!(tree.find_by_path(&link.join("y.md")).is_some());
+
+    fs::write(target.join("z.md"), "z").unwrap();
+    tree.refresh_subtree(link_id).unwrap();
+    let node = tree.inner()[link_id].get();
+    assert_eq!(node.path(), link);
+    assert!(node.is_dir() && node.is_symlink());
+    assert!(tree.find_by_path(&link.join("y.md")).is_some());
+    assert!(tree.find_by_path(&link.join("z.md")).is_some());
+    let target = canonicalize(&target).unwrap();
+    assert!(tree.find_by_path(&target.join("z.md")).is_none());
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn test_follow_links_prefers_directory() {
+    let root = tree_fixture("links_prefer_dir", &[("notes/x.md", "x")]);
+    std::os::unix::fs::symlink(root.join("notes"), root.join("alias")).unwrap();
+    let opts = WalkOptions {
+      follow_links: true,
+      threads: Some(4),
+      ..WalkOptions::default()
+    };
+    let root = canonicalize(&root).unwrap();
+    for _ in 0..10 {
+      let tree = Tree::init(root.to_str().unwrap(), None, false, &opts).unwrap();
+      assert!(tree.find_by_path(&root.join("notes/x.md")).is_some());
+      assert!(tree.find_by_path(&root.join("alias")).is_none());
+      assert_eq!(tree.iter().filter(|n| n.file_name() == "x.md").count(), 1);
+    }
+  }
+
+  #[test]
+  fn test_tree_to_json_deep() {
+    use indextree::Arena;
+
+    // far deeper than the stack would take one frame per level
+    let mut inner = Arena::new();
+    let node = |depth: usize| {
+      let path = PathBuf::from(format!("/deep/{depth}"));
+      Node::new(depth, depth.to_string().into(), None, None, None, path)
+    };
+    let root = inner.new_node(node(0));
+    let mut parent = root;
+    for depth in 1..=50_000 {
+      let id = inner.new_node(node(depth));
+      parent.append(id, &mut inner);
+      parent = id;
+    }
+    let tree = Tree::new(inner, root);
+
+    let json = tree.to_json().unwrap();
+    assert!(json.starts_with("{\"depth\":0,"));
+    assert_eq!(json.matches("\"children\":[").count(), 50_001);
+    assert!(json.ends_with(&"]}".repeat(50_001)));
+  }
 }
@@ -0,0 +1,85 @@
+use super::{
+  options::{ReadMode, ReadPredicate, WalkOptions},
+  Tree, TreeResult,
+};
+use std::path::Path;
+
+/// Fluent setup of a traversal, the defaults match `Tree::init` with no
+/// depth limit, no content and [WalkOptions::default].
+#[derive(Debug, Clone, Default)]
+pub struct TreeBuilder {
+  depth: Option<usize>,
+  read_ctn: ReadMode,
+  opts: WalkOptions,
+}
+
+impl TreeBuilder {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  // Starts from `opts` for the knobs without a setter
+  pub fn options(mut self, opts: WalkOptions) -> Self {
+    self.opts = opts;
+    self
+  }
+
+  pub fn depth(mut self, depth: usize) -> Self {
+    self.depth = Some(depth);
+    self
+  }
+
+  // A bool means all or nothing, see [ReadMode]
+  pub fn read_content(mut self, read_ctn: impl Into<ReadMode>) -> Self {
+    self.read_ctn = read_ctn.into();
+    self
+  }
+
+  // Reads content in full, only for the files `predicate` accepts
+  pub fn read_content_if<F>(mut self, predicate: F) -> Self
+  where
+    F: Fn(&Path) -> bool + Send + Sync + 'static,
+  {
+    if self.read_ctn.is_none() {
+      self.read_ctn = ReadMode::Full;
+    }
+    self.opts.read_if = Some(ReadPredicate::new(predicate));
+    self
+  }
+
+  pub fn show_hidden(mut self, show_hidden: bool) -> Self {
+    self.opts.show_hidden = show_hidden;
+    self
+  }
+
+  pub fn follow_links(mut self, follow_links: bool) -> Self {
+    self.opts.follow_links = follow_links;
+    self
+  }
+
+  pub fn threads(mut self, threads: usize) -> Self {
+    self.opts.threads = Some(threads);
+    self
+  }
+
+  pub fn include_globs<I, S>(mut self, globs: I) -> Self
+  where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+  {
+    self.opts.include_globs = globs.into_iter().map(Into::into).collect();
+    self
+  }
+
+  // Walks `dir` with the collected settings.
+  pub fn build(&self, dir: &str) -> TreeResult<Tree> {
+    Tree::init(dir, self.depth, self.read_ctn, &self.opts)
+  }
+}
+
+impl Tree {
+  // Shorthand for [TreeBuilder::new].
+  pub fn builder() -> TreeBuilder {
+    TreeBuilder::new()
+  }
+}
@@ -0,0 +1,106 @@
+use super::{
+  node::Node,
+  options::{ReadMode, WalkOptions},
+  Tree, TreeError, TreeResult,
+};
+use indextree::{Arena, NodeId};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, path::Path};
+
+// bumped on changes of the format, other versions are refused
+const CACHE_VERSION: u32 = 2;
+
+// Tree on disk, nodes in pre-order next to the index of their parent
+#[derive(Serialize, Deserialize)]
+struct Cache<N> {
+  version: u32,
+  read_ctn: ReadMode,
+  // walked again by `expand` and `refresh_subtree`
+  opts: WalkOptions,
+  depth: Option<usize>,
+  truncated: bool,
+  nodes: Vec<(N, Option<usize>)>,
+}
+
+impl Tree {
+  // Writes the tree as JSON to `path`, to be restored by `load_cache`.
+  pub fn save_cache(&self, path: &Path) -> TreeResult<()> {
+    let mut index: HashMap<NodeId, usize> = HashMap::new();
+    let mut nodes = Vec::new();
+    for id in self.root.descendants(&self.inner) {
+      let parent = self.inner[id].parent().and_then(|p| index.get(&p).copied());
+      index.insert(id, nodes.len());
+      nodes.push((self.inner[id].get(), parent));
+    }
+
+    let cache = Cache {
+      version: CACHE_VERSION,
+      read_ctn: self.read_ctn,
+      opts: self.opts.clone(),
+      depth: self.depth,
+      truncated: self.truncated,
+      nodes,
+    };
+    let json = serde_json::to_vec(&cache).map_err(TreeError::Json)?;
+    fs::write(path, json).map_err(|e| TreeError::from_io(e, path))
+  }
+
+  // Restores a tree written by `save_cache`, along with its walk options
+  // save `WalkOptions::read_if`. File metadata isn't cached; check
+  // `is_stale` before trusting the result.
+  pub fn load_cache(path: &Path) -> TreeResult<Self> {
+    let json = fs::read(path).map_err(|e| TreeError::from_io(e, path))?;
+    let cache: Cache<Node> =
+      serde_json::from_slice(&json).map_err(TreeError::Json)?;
+    if cache.version != CACHE_VERSION {
+      let reason = format!("Unsupported cache version {}", cache.version);
+      return Err(TreeError::Unreadable(path.to_owned(), reason));
+    }
+
+    let mut inner = Arena::new();
+    let mut ids: Vec<NodeId> = Vec::with_capacity(cache.nodes.len());
+    for (node, parent) in cache.nodes {
+      let id = inner.new_node(node);
+      match parent.and_then(|idx| ids.get(idx)) {
+        Some(parent_id) => parent_id.append(id, &mut inner),
+        None if ids.is_empty() => {}
+        None => {
+          let reason = "Node without parent".to_string();
+          return Err(TreeError::Unreadable(path.to_owned(), reason));
+        }
+      }
+      ids.push(id);
+    }
+    let root = *ids.first().ok_or(TreeError::MissingRoot)?;
+
+    let mut tree = Self::new(inner, root);
+    tree.read_ctn = cache.read_ctn;
+    tree.opts = cache.opts;
+    tree.depth = cache.depth;
+    tree.truncated = cache.truncated;
+    Ok(tree)
+  }
+
+  // If a root directory is gone or its modification time changed since the
+  // traversal, e.g. for an entry added or removed right in it. Entries
+  // further down aren't checked.
+  pub fn is_stale(&self) -> bool {
+    let root = self.inner[self.root].get();
+    let roots: Vec<&Node> = if root.is_virtual() {
+      self
+        .root
+        .children(&self.inner)
+        .map(|id| self.inner[id].get())
+        .collect()
+    } else {
+      vec![root]
+    };
+
+    roots.into_iter().any(|node| {
+      match fs::metadata(node.path()).and_then(|meta| meta.modified()) {
+        Ok(modified) => node.modified() != Some(modified),
+        Err(_) => true,
+      }
+    })
+  }
+}
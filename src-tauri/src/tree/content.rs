@@ -0,0 +1,410 @@
+// Parsers for the text of notes, all tolerant of malformed input.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+/// Flavor of a frontmatter block, told by how it opens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrontmatterFormat {
+  // fenced by `---`, closed by `---` or `...`
+  Yaml,
+  // fenced by `+++`
+  Toml,
+  // an object right at the start
+  Json,
+}
+
+// Splits a leading frontmatter block off, returns (frontmatter, body)
+pub fn split_frontmatter(text: &str) -> Option<(&str, &str)> {
+  frontmatter_block(text).map(|(_, block, body)| (block, body))
+}
+
+// Same as `split_frontmatter`, also telling the format
+pub fn frontmatter_block(text: &str) -> Option<(FrontmatterFormat, &str, &str)> {
+  let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+  if text.starts_with('{') {
+    // where the object ends takes parsing it
+    let mut values =
+      serde_json::Deserializer::from_str(text).into_iter::<serde_json::Value>();
+    return match values.next() {
+      Some(Ok(_)) => {
+        let (block, body) = text.split_at(values.byte_offset());
+        let body = body
+          .strip_prefix("\r\n")
+          .or_else(|| body.strip_prefix('\n'))
+          .unwrap_or(body);
+        Some((FrontmatterFormat::Json, block, body))
+      }
+      _ => None,
+    };
+  }
+
+  let opening = text.split_inclusive('\n').next()?;
+  let (format, fences): (_, &[&str]) = match opening.trim_end() {
+    "---" => (FrontmatterFormat::Yaml, &["---", "..."]),
+    "+++" => (FrontmatterFormat::Toml, &["+++"]),
+    _ => return None,
+  };
+  if !opening.ends_with('\n') {
+    return None;
+  }
+  let rest = &text[opening.len()..];
+
+  let mut offset = 0;
+  for line in rest.split_inclusive('\n') {
+    if fences.contains(&line.trim_end()) {
+      let body = &rest[offset + line.len()..];
+      return Some((format, &rest[..offset], body));
+    }
+    offset += line.len();
+  }
+
+  None
+}
+
+// Parses YAML, TOML or JSON frontmatter into a JSON object, None if missing
+// or malformed
+pub fn parse_frontmatter(text: &str) -> Option<serde_json::Value> {
+  let (format, block, _) = frontmatter_block(text)?;
+  let value = match format {
+    FrontmatterFormat::Yaml => serde_yaml::from_str(block).ok()?,
+    FrontmatterFormat::Toml => toml_to_json(toml::from_str(block).ok()?),
+    FrontmatterFormat::Json => serde_json::from_str(block).ok()?,
+  };
+  Some(value).filter(|value: &serde_json::Value| value.is_object())
+}
+
+// Dates become strings, as they are in YAML
+fn toml_to_json(value: toml::Value) -> serde_json::Value {
+  use serde_json::Value as Json;
+
+  match value {
+    toml::Value::String(s) => Json::String(s),
+    toml::Value::Integer(i) => Json::from(i),
+    toml::Value::Float(f) => Json::from(f),
+    toml::Value::Boolean(b) => Json::Bool(b),
+    toml::Value::Datetime(dt) => Json::String(dt.to_string()),
+    toml::Value::Array(values) => {
+      Json::Array(values.into_iter().map(toml_to_json).collect())
+    }
+    toml::Value::Table(table) => Json::Object(
+      table
+        .into_iter()
+        .map(|(key, value)| (key, toml_to_json(value)))
+        .collect(),
+    ),
+  }
+}
+
+// Lines outside of fenced code blocks, with their 0-based line index
+pub fn prose_lines(text: &str) -> Vec<(usize, &str)> {
+  let mut fence: Option<&str> = None;
+  let mut lines = Vec::new();
+
+  for (idx, line) in text.lines().enumerate() {
+    let trimmed = line.trim_start();
+    match fence {
+      Some(marker) => {
+        if trimmed.starts_with(marker) {
+          fence = None;
+        }
+      }
+      None => {
+        if trimmed.starts_with("```") {
+          fence = Some("```");
+        } else if trimmed.starts_with("~~~") {
+          fence = Some("~~~");
+        } else {
+          lines.push((idx, line));
+        }
+      }
+    }
+  }
+
+  lines
+}
+
+// Info string language of each fenced code block, lowercased and empty for
+// unlabeled fences, e.g. `rust` for ```` ```rust,ignore ````
+pub fn code_languages(text: &str) -> Vec<String> {
+  let body = split_frontmatter(text).map_or(text, |(_, body)| body);
+  let mut fence: Option<&str> = None;
+  let mut languages = Vec::new();
+
+  for line in body.lines() {
+    let trimmed = line.trim_start();
+    match fence {
+      Some(marker) => {
+        if trimmed.starts_with(marker) {
+          fence = None;
+        }
+      }
+      None => {
+        let marker = ["```", "~~~"]
+          .into_iter()
+          .find(|marker| trimmed.starts_with(*marker));
+        if let Some(marker) = marker {
+          fence = Some(marker);
+          let language = trimmed[marker.len()..]
+            .trim_start_matches(['`', '~'])
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .find(|token| !token.is_empty())
+            .unwrap_or("");
+          languages.push(language.to_lowercase());
+        }
+      }
+    }
+  }
+
+  languages
+}
+
+/// Heading of a note, `#` or setext style.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Heading {
+  // 1 to 6, setext headings are 1 or 2
+  pub level: u8,
+  pub text: String,
+  // 1-based, frontmatter lines included
+  pub line: usize,
+}
+
+// Headings outside of fenced code in order of appearance, empty ones left
+// out. A setext underline only counts below a line of text.
+pub fn headings(text: &str) -> Vec<Heading> {
+  let body = split_frontmatter(text).map_or(text, |(_, body)| body);
+  // lines of the frontmatter block before `body`
+  let offset = text[..text.len() - body.len()].matches('\n').count();
+
+  let mut headings: Vec<Heading> = Vec::new();
+  let mut previous: Option<(usize, &str)> = None;
+  for (idx, line) in prose_lines(body) {
+    let indented = line.len() - line.trim_start().len() > 3;
+    let trimmed = line.trim();
+
+    if let Some(heading) = (!indented).then(|| atx_heading(trimmed)).flatten() {
+      let (level, text) = heading;
+      if !text.is_empty() {
+        headings.push(Heading {
+          level,
+          text: text.to_string(),
+          line: offset + idx + 1,
+        });
+      }
+      previous = None;
+      continue;
+    }
+
+    let underline = match trimmed.chars().next() {
+      Some('=') if !indented && trimmed.chars().all(|c| c == '=') => Some(1),
+      Some('-') if !indented && trimmed.chars().all(|c| c == '-') => Some(2),
+      _ => None,
+    };
+    match (underline, previous) {
+      (Some(level), Some((prev_idx, prev))) if prev_idx + 1 == idx => {
+        headings.push(Heading {
+          level,
+          text: prev.to_string(),
+          line: offset + prev_idx + 1,
+        });
+        previous = None;
+      }
+      _ => previous = (!trimmed.is_empty()).then_some((idx, trimmed)),
+    }
+  }
+
+  headings
+}
+
+// Level and text of an ATX heading line, closing `#`s dropped
+fn atx_heading(line: &str) -> Option<(u8, &str)> {
+  let level = line.chars().take_while(|c| *c == '#').count();
+  let rest = &line[level..];
+  if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with(' ')) {
+    return None;
+  }
+
+  let rest = rest.trim();
+  let closed = rest.trim_end_matches('#');
+  let text = if closed.is_empty() || closed.ends_with(' ') {
+    closed.trim_end()
+  } else {
+    rest
+  };
+  Some((level as u8, text))
+}
+
+// Text of the first level-1 ATX heading outside of code
+pub fn first_heading(text: &str) -> Option<String> {
+  let body = split_frontmatter(text).map_or(text, |(_, body)| body);
+  prose_lines(body).into_iter().find_map(|(_, line)| {
+    let heading = line.strip_prefix("# ")?;
+    let heading = heading.trim().trim_end_matches('#').trim();
+    (!heading.is_empty()).then(|| heading.to_string())
+  })
+}
+
+// Words of the body, frontmatter, fenced code, link targets and markdown
+// tokens like `#` or `*` excluded
+pub fn word_count(text: &str) -> usize {
+  let body = split_frontmatter(text).map_or(text, |(_, body)| body);
+  prose_lines(body)
+    .into_iter()
+    .map(|(_, line)| {
+      strip_link_targets(line)
+        .split_whitespace()
+        .filter(|token| {
+          !token.starts_with("http://") && !token.starts_with("https://")
+        })
+        .filter(|token| token.chars().any(|c| c.is_alphanumeric()))
+        .count()
+    })
+    .sum()
+}
+
+// Drops the `(target)` of `[text](target)`
+fn strip_link_targets(line: &str) -> String {
+  let mut res = String::with_capacity(line.len());
+  let mut rest = line;
+  while let Some(idx) = rest.find("](") {
+    res.push_str(&rest[..=idx]);
+    rest = &rest[idx + 2..];
+    match rest.find(')') {
+      Some(end) => rest = &rest[end + 1..],
+      None => rest = "",
+    }
+  }
+  res.push_str(rest);
+  res
+}
+
+// Inline `#tags` of the body without the `#`, de-duplicated in order of
+// appearance. Headings, code and URL fragments are no tags.
+pub fn tags(text: &str) -> Vec<String> {
+  static TAG: OnceLock<Regex> = OnceLock::new();
+  let tag = TAG.get_or_init(|| {
+    Regex::new(r"(?:^|[\s(\[,])#([\p{L}\p{N}_/-]*[\p{L}_][\p{L}\p{N}_/-]*)")
+      .expect("valid tag pattern")
+  });
+
+  let body = split_frontmatter(text).map_or(text, |(_, body)| body);
+  let mut tags: Vec<String> = Vec::new();
+  for (_, line) in prose_lines(body) {
+    let line = strip_code_spans(line);
+    for cap in tag.captures_iter(&line) {
+      let name = cap[1].trim_end_matches(['/', '-']);
+      if !tags.iter().any(|t| t == name) {
+        tags.push(name.to_string());
+      }
+    }
+  }
+
+  tags
+}
+
+// Targets of `[[wikilinks]]` outside of code, de-duplicated in order of
+// appearance. Aliases and `#heading` or `^block` anchors are dropped.
+pub fn wikilinks(text: &str) -> Vec<String> {
+  let body = split_frontmatter(text).map_or(text, |(_, body)| body);
+  let mut links: Vec<String> = Vec::new();
+  for (_, line) in prose_lines(body) {
+    let line = strip_code_spans(line);
+    let mut rest = line.as_str();
+    while let Some(start) = rest.find("[[") {
+      rest = &rest[start + 2..];
+      let end = match rest.find("]]") {
+        Some(end) => end,
+        None => break,
+      };
+      let inner = &rest[..end];
+      rest = &rest[end + 2..];
+
+      let target = inner.split('|').next().unwrap_or_default();
+      let target = target.split(['#', '^']).next().unwrap_or_default().trim();
+      // `[[#Section]]` points into the note itself
+      if !target.is_empty() && !links.iter().any(|l| l == target) {
+        links.push(target.to_string());
+      }
+    }
+  }
+
+  links
+}
+
+// Targets of inline `[text](target)` links outside of code as written,
+// de-duplicated in order of appearance. `![alt](target)` images only with
+// `images`.
+pub fn md_links(text: &str, images: bool) -> Vec<String> {
+  static LINK: OnceLock<Regex> = OnceLock::new();
+  let link = LINK.get_or_init(|| {
+    Regex::new(
+      r#"(!?)\[[^\]]*\]\(\s*(?:<([^>]*)>|([^\s()]+))(?:\s+(?:"[^"]*"|'[^']*'))?\s*\)"#,
+    )
+    .expect("valid link pattern")
+  });
+
+  let body = split_frontmatter(text).map_or(text, |(_, body)| body);
+  let mut links: Vec<String> = Vec::new();
+  for (_, line) in prose_lines(body) {
+    let line = strip_code_spans(line);
+    for cap in link.captures_iter(&line) {
+      if !images && !cap[1].is_empty() {
+        continue;
+      }
+      let target = cap.get(2).or_else(|| cap.get(3)).map_or("", |m| m.as_str());
+      if !target.is_empty() && !links.iter().any(|l| l == target) {
+        links.push(target.to_string());
+      }
+    }
+  }
+
+  links
+}
+
+// `http(s)://` URLs outside of code, in links as well as bare or in `<>`,
+// de-duplicated in order of appearance. Trailing punctuation is taken to
+// end the sentence.
+pub fn external_urls(text: &str) -> Vec<String> {
+  static URL: OnceLock<Regex> = OnceLock::new();
+  let url = URL.get_or_init(|| {
+    Regex::new(r#"https?://[^\s<>()\[\]"'`]+"#).expect("valid url pattern")
+  });
+
+  let body = split_frontmatter(text).map_or(text, |(_, body)| body);
+  let mut urls: Vec<String> = Vec::new();
+  for (_, line) in prose_lines(body) {
+    let line = strip_code_spans(line);
+    for found in url.find_iter(&line) {
+      let found = found
+        .as_str()
+        .trim_end_matches(['.', ',', ';', ':', '!', '?']);
+      let is_bare_scheme = found.ends_with("://");
+      if !is_bare_scheme && !urls.iter().any(|u| u == found) {
+        urls.push(found.to_string());
+      }
+    }
+  }
+
+  urls
+}
+
+// Blanks out inline `code` spans, unclosed backticks are kept
+fn strip_code_spans(line: &str) -> String {
+  let mut res = String::with_capacity(line.len());
+  let mut rest = line;
+  while let Some(start) = rest.find('`') {
+    let ticks = rest[start..].len() - rest[start..].trim_start_matches('`').len();
+    let fence = &rest[start..start + ticks];
+    match rest[start + ticks..].find(fence) {
+      Some(end) => {
+        res.push_str(&rest[..start]);
+        res.push(' ');
+        rest = &rest[start + ticks + end + ticks..];
+      }
+      None => break,
+    }
+  }
+  res.push_str(rest);
+  res
+}
@@ -0,0 +1,130 @@
+use super::{node::Node, Tree};
+use std::{
+  collections::{BTreeMap, HashMap, HashSet, VecDeque},
+  path::PathBuf,
+};
+
+/// Changes from one [Tree] snapshot to another, by path relative to the
+/// roots. Each list is sorted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TreeDiff {
+  pub added: Vec<PathBuf>,
+  pub removed: Vec<PathBuf>,
+  // files only
+  pub modified: Vec<PathBuf>,
+  // files removed and added with the same content, by `from`
+  pub renamed: Vec<Renamed>,
+}
+
+/// File which moved between two snapshots.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Renamed {
+  pub from: PathBuf,
+  pub to: PathBuf,
+}
+
+impl Tree {
+  // Compares this older snapshot with `other`, the roots may differ.
+  //
+  // A file is modified if the content hashes differ, when both trees read the
+  // content in full, else if the modification times differ, else if the sizes
+  // do. A file turned directory, or the other way round, is removed and added.
+  //
+  // A removed and an added file with the same content hash are a rename,
+  // which needs the content of both trees read in full. Empty files are
+  // never paired.
+  pub fn diff(&self, other: &Tree) -> TreeDiff {
+    let before = self.relative_nodes();
+    let after = other.relative_nodes();
+    let mut diff = TreeDiff::default();
+
+    for (path, old) in &before {
+      match after.get(path) {
+        Some(new) if old.is_dir() != new.is_dir() => {
+          diff.removed.push(path.clone());
+          diff.added.push(path.clone());
+        }
+        Some(new) => {
+          if !old.is_dir() && is_modified(old, new) {
+            diff.modified.push(path.clone());
+          }
+        }
+        None => diff.removed.push(path.clone()),
+      }
+    }
+    let added = after.keys().filter(|path| !before.contains_key(*path));
+    diff.added.extend(added.cloned());
+    diff.added.sort();
+
+    detect_renames(&mut diff, &before, &after);
+    diff
+  }
+
+  // Nodes below the root by their relative path
+  fn relative_nodes(&self) -> BTreeMap<PathBuf, &Node> {
+    self
+      .root
+      .descendants(&self.inner)
+      .skip(1)
+      .filter_map(|id| Some((self.relative_path(id)?, self.inner[id].get())))
+      .collect()
+  }
+}
+
+// Pairs removed and added files by content, each file once
+fn detect_renames(
+  diff: &mut TreeDiff,
+  before: &BTreeMap<PathBuf, &Node>,
+  after: &BTreeMap<PathBuf, &Node>,
+) {
+  let mut added_by_hash: HashMap<u64, VecDeque<&PathBuf>> = HashMap::new();
+  for path in &diff.added {
+    if let Some(hash) = after.get(path).and_then(|node| rename_key(node)) {
+      added_by_hash.entry(hash).or_default().push_back(path);
+    }
+  }
+
+  let mut removed = Vec::new();
+  let mut renamed = Vec::new();
+  for from in diff.removed.drain(..) {
+    let to = before
+      .get(&from)
+      .and_then(|node| rename_key(node))
+      .and_then(|hash| added_by_hash.get_mut(&hash)?.pop_front());
+    match to {
+      Some(to) => renamed.push(Renamed {
+        from,
+        to: to.clone(),
+      }),
+      None => removed.push(from),
+    }
+  }
+
+  let moved: HashSet<&PathBuf> = renamed.iter().map(|r| &r.to).collect();
+  let added = diff
+    .added
+    .iter()
+    .filter(|path| !moved.contains(path))
+    .cloned()
+    .collect();
+  diff.added = added;
+  diff.removed = removed;
+  diff.renamed = renamed;
+}
+
+// What a file is recognized by after a rename
+fn rename_key(node: &Node) -> Option<u64> {
+  node
+    .content_hash
+    .filter(|_| !node.is_dir() && node.size > 0)
+}
+
+fn is_modified(old: &Node, new: &Node) -> bool {
+  if let (Some(old_hash), Some(new_hash)) = (old.content_hash, new.content_hash) {
+    return old_hash != new_hash;
+  }
+  if let (Some(old_time), Some(new_time)) = (old.modified(), new.modified()) {
+    return old_time != new_time;
+  }
+  old.size != new.size
+}
@@ -0,0 +1,451 @@
+use super::{
+  canonical_path, count_children, node::Node, Traversal, TraverseHooks, Tree,
+  TreeError, TreeResult,
+};
+use indextree::{NodeEdge, NodeId};
+use std::{
+  fs, io,
+  path::{Component, Path, PathBuf},
+};
+
+impl Tree {
+  // Splices the node of an existing path on disk into the tree, the parent
+  // directory must already be in the tree. Returns the present node if any.
+  pub fn insert_path(&mut self, path: &Path) -> TreeResult<NodeId> {
+    let path = canonical_path(path).map_err(|e| TreeError::from_io(e, path))?;
+    if let Some(existing) = self.paths.get(&path).copied() {
+      return Ok(existing);
+    }
+
+    let parent_path = path
+      .parent()
+      .ok_or_else(|| TreeError::ExpectedParent(path.clone()))?;
+    let parent_id = self
+      .paths
+      .get(parent_path)
+      .copied()
+      .filter(|id| self.inner[*id].get().is_dir())
+      .ok_or_else(|| TreeError::ExpectedParent(path.clone()))?;
+
+    let depth = self.inner[parent_id].get().depth + 1;
+    let node = Node::from_path(&path, depth, &self.content_reader())
+      .map_err(|e| TreeError::from_io(e, &path))?;
+    let node_id = self.inner.new_node(node);
+    parent_id.append(node_id, &mut self.inner);
+    count_children(&mut self.inner, parent_id);
+    self.paths.insert(path, node_id);
+    self.assign_uid(node_id);
+
+    Ok(node_id)
+  }
+
+  // Copies the subtree of `node_id` in `other` below `parent`, with the
+  // depths shifted to fit. Returns the id of the copy of `node_id`.
+  pub(crate) fn graft(
+    &mut self,
+    parent: NodeId,
+    other: &Tree,
+    node_id: NodeId,
+  ) -> NodeId {
+    let base = other.inner[node_id].get().depth;
+    let offset = self.inner[parent].get().depth + 1;
+    let mut grafted = parent;
+    let mut stack = vec![(node_id, parent)];
+
+    while let Some((other_id, new_parent)) = stack.pop() {
+      let mut node = other.inner[other_id].get().clone();
+      node.depth = node.depth - base + offset;
+      let path = node.path().to_owned();
+      let new_id = self.inner.new_node(node);
+      new_parent.append(new_id, &mut self.inner);
+      self.paths.insert(path, new_id);
+      self.assign_uid(new_id);
+      if other_id == node_id {
+        grafted = new_id;
+      }
+
+      // reversed, siblings are popped in order
+      for child_id in other_id.reverse_children(&other.inner) {
+        stack.push((child_id, new_id));
+      }
+    }
+
+    count_children(&mut self.inner, parent);
+    grafted
+  }
+
+  // Takes the nodes of `other` into the tree: its root merges into the
+  // directory at the same path, else it goes below the directory of its
+  // parent path, else below the root. Directories in both are merged, for
+  // other paths in both the incoming node wins, `other` replaces the whole
+  // tree if that is the root. Returns those overwritten paths.
+  pub fn merge(&mut self, other: Tree) -> Vec<PathBuf> {
+    let other_root = other.inner[other.root].get();
+    let same = self.paths.get(other_root.path()).copied();
+    let target = match same {
+      Some(id) if other_root.is_dir() && self.inner[id].get().is_dir() => id,
+      // changed between file and directory, nothing is left to graft on
+      Some(id) if id == self.root => {
+        let overwritten = vec![other_root.path().to_owned()];
+        *self = other;
+        return overwritten;
+      }
+      _ => {
+        let parent = other_root
+          .parent_path()
+          .and_then(|parent| self.paths.get(parent).copied())
+          .filter(|id| self.inner[*id].get().is_dir())
+          .unwrap_or(self.root);
+        let mut overwritten = Vec::new();
+        if let Some(id) = same {
+          overwritten.push(other_root.path().to_owned());
+          self.remove_subtree(id);
+        }
+        self.graft(parent, &other, other.root);
+        return overwritten;
+      }
+    };
+
+    let mut overwritten = Vec::new();
+    let mut stack = vec![(target, other.root)];
+    while let Some((dir_id, other_id)) = stack.pop() {
+      self.replace_node(dir_id, other.inner[other_id].get());
+
+      for child_id in other_id.children(&other.inner) {
+        let child = other.inner[child_id].get();
+        let existing = match self.paths.get(child.path()).copied() {
+          Some(id) => id,
+          None => {
+            self.graft(dir_id, &other, child_id);
+            continue;
+          }
+        };
+        match (self.inner[existing].get().is_dir(), child.is_dir()) {
+          (true, true) => stack.push((existing, child_id)),
+          (false, false) => {
+            overwritten.push(child.path().to_owned());
+            self.replace_node(existing, child);
+          }
+          // changed between file and directory
+          _ => {
+            overwritten.push(child.path().to_owned());
+            self.remove_subtree(existing);
+            self.graft(dir_id, &other, child_id);
+          }
+        }
+      }
+    }
+
+    overwritten.sort();
+    overwritten
+  }
+
+  // Puts a copy of `node` in place of the one of `node_id`, which keeps its
+  // depth and children
+  fn replace_node(&mut self, node_id: NodeId, node: &Node) {
+    let mut node = node.clone();
+    node.depth = self.inner[node_id].get().depth;
+    *self.inner[node_id].get_mut() = node;
+    count_children(&mut self.inner, node_id);
+    self.assign_uid(node_id);
+  }
+
+  // Walks the children of a directory not loaded yet, see
+  // `WalkOptions::lazy_dirs`, and splices them in with the options of the
+  // tree. The subdirectories found are left unloaded. Returns the children,
+  // also if they were loaded before.
+  pub fn expand(&mut self, node_id: NodeId) -> TreeResult<Vec<NodeId>> {
+    let node = self.get_node(node_id)?;
+    if !node.is_dir() {
+      return Err(TreeError::ExpectedParent(node.path().to_owned()));
+    }
+
+    if !node.is_loaded() {
+      let dir = node.path().to_owned();
+      let glob_root = self
+        .top_dir(node_id)
+        .map(|id| self.inner[id].get().path().to_owned());
+      let hooks = TraverseHooks::default();
+      let Traversal { inner, root, .. } = Self::traverse(
+        &dir,
+        Some(1),
+        self.read_ctn,
+        &self.opts,
+        glob_root.as_deref(),
+        &hooks,
+      )?;
+
+      let listing = Tree::new(inner, root);
+      for child_id in root.children(&listing.inner) {
+        if !self
+          .paths
+          .contains_key(listing.inner[child_id].get().path())
+        {
+          self.graft(node_id, &listing, child_id);
+        }
+      }
+      self.inner[node_id].get_mut().set_loaded(true);
+    }
+
+    Ok(node_id.children(&self.inner).collect())
+  }
+
+  // Walks the directory of `node_id` again with the options of the tree
+  // and replaces its subtree, e.g. after a `git pull`. The node keeps its
+  // id with its own metadata refreshed, the rest of the tree is untouched.
+  pub fn refresh_subtree(&mut self, node_id: NodeId) -> TreeResult<()> {
+    let node = self.get_node(node_id)?;
+    if !node.is_dir() || node.is_virtual() {
+      return Err(TreeError::ExpectedParent(node.path().to_owned()));
+    }
+
+    let dir = node.path().to_owned();
+    let depth = node.depth;
+    let glob_root = self
+      .top_dir(node_id)
+      .map(|id| self.inner[id].get().path().to_owned());
+    let hooks = TraverseHooks::default();
+    let Traversal { inner, root, .. } = Self::traverse(
+      &dir,
+      self.depth.map(|max| max.saturating_sub(depth)),
+      self.read_ctn,
+      &self.opts,
+      glob_root.as_deref(),
+      &hooks,
+    )?;
+    let fresh = Tree::new(inner, root);
+    // the walk doesn't follow a link at its root, the node tells it as is
+    let mut node = Node::from_path(&dir, depth, &self.content_reader())
+      .map_err(|e| TreeError::from_io(e, &dir))?;
+    node.set_loaded(true);
+
+    let stale: Vec<NodeId> = node_id.children(&self.inner).collect();
+    for child_id in stale {
+      self.remove_subtree(child_id);
+    }
+    *self.inner[node_id].get_mut() = node;
+    self.assign_uid(node_id);
+    for child_id in root.children(&fresh.inner) {
+      self.graft(node_id, &fresh, child_id);
+    }
+
+    if self.opts.prune_empty_dirs {
+      self.prune_empty();
+    }
+    Ok(())
+  }
+
+  // Creates an empty file or a directory named `name` in the directory of
+  // `parent` on disk and in the tree. Never overwrites.
+  pub fn create(
+    &mut self,
+    parent: NodeId,
+    name: &str,
+    is_dir: bool,
+  ) -> TreeResult<NodeId> {
+    let mut components = Path::new(name).components();
+    let is_plain = matches!(
+      (components.next(), components.next()),
+      (Some(Component::Normal(_)), None)
+    );
+    if !is_plain || name.contains(['/', '\\']) {
+      return Err(TreeError::InvalidName(name.to_string()));
+    }
+
+    let parent_node = self.get_node(parent)?;
+    if !parent_node.is_dir() {
+      return Err(TreeError::ExpectedParent(parent_node.path().join(name)));
+    }
+    let path = parent_node.path().join(name);
+
+    let created = if is_dir {
+      fs::create_dir(&path)
+    } else {
+      fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .map(|_| ())
+    };
+    created.map_err(|e| TreeError::from_io(e, &path))?;
+
+    self.insert_path(&path)
+  }
+
+  // Drops the node at `path` with its entire subtree, e.g. after it was
+  // deleted on disk.
+  pub fn remove_path(&mut self, path: &Path) -> TreeResult<()> {
+    let path = canonical_path(path).unwrap_or_else(|_| path.to_owned());
+    let node_id = self
+      .paths
+      .get(&path)
+      .copied()
+      .ok_or_else(|| TreeError::NotFound(path.clone()))?;
+    if node_id == self.root {
+      return Err(TreeError::ExpectedParent(path));
+    }
+
+    self.remove_subtree(node_id);
+    Ok(())
+  }
+
+  // Re-reads the entry at `path` after it changed on disk and refreshes its
+  // node in place, the id and the children stay. The tag and link indexes
+  // are derived from the nodes, so they follow. An entry deleted meanwhile
+  // is dropped like with `remove_path`.
+  pub fn reindex_path(&mut self, path: &Path) -> TreeResult<()> {
+    let path = canonical_path(path).unwrap_or_else(|_| path.to_owned());
+    let node_id = self
+      .paths
+      .get(&path)
+      .copied()
+      .ok_or_else(|| TreeError::NotFound(path.clone()))?;
+
+    let old = self.inner[node_id].get();
+    let (depth, recursive_size, is_loaded) =
+      (old.depth, old.recursive_size, old.is_loaded());
+    let node = match Node::from_path(&path, depth, &self.content_reader()) {
+      Ok(node) => node,
+      Err(e) if e.kind() == io::ErrorKind::NotFound && node_id != self.root => {
+        self.remove_subtree(node_id);
+        return Ok(());
+      }
+      Err(e) => return Err(TreeError::from_io(e, &path)),
+    };
+
+    let slot = self.inner[node_id].get_mut();
+    *slot = node;
+    // describe the subtree, not the entry
+    slot.recursive_size = recursive_size;
+    slot.set_loaded(is_loaded);
+    count_children(&mut self.inner, node_id);
+    self.assign_uid(node_id);
+    Ok(())
+  }
+
+  // Moves the entry of `node_id` to the trash of the OS and drops its
+  // subtree, a directory goes as a whole. The root can't be trashed.
+  pub fn trash(&mut self, node_id: NodeId) -> TreeResult<()> {
+    let path = self.get_node(node_id)?.path().to_owned();
+    if node_id == self.root {
+      return Err(TreeError::ExpectedParent(path));
+    }
+
+    trash::delete(&path).map_err(TreeError::Trash)?;
+    self.remove_subtree(node_id);
+    Ok(())
+  }
+
+  // Renames or moves the entry of `node_id` to `new_path` on disk and in the
+  // tree, the paths and depths of all descendants follow. The directory of
+  // `new_path` must be in the tree and `new_path` must not exist yet.
+  pub fn rename(&mut self, node_id: NodeId, new_path: &Path) -> TreeResult<()> {
+    let old_path = self.get_node(node_id)?.path().to_owned();
+    if node_id == self.root {
+      return Err(TreeError::ExpectedParent(old_path));
+    }
+
+    let new_path =
+      canonical_path(new_path).map_err(|e| TreeError::from_io(e, new_path))?;
+    if new_path.starts_with(&old_path) {
+      return Err(TreeError::ExpectedParent(new_path));
+    }
+    if fs::symlink_metadata(&new_path).is_ok() {
+      return Err(TreeError::AlreadyExists(new_path));
+    }
+    let new_parent = new_path
+      .parent()
+      .and_then(|parent| self.paths.get(parent).copied())
+      .filter(|id| self.inner[*id].get().is_dir())
+      .ok_or_else(|| TreeError::ExpectedParent(new_path.clone()))?;
+
+    fs::rename(&old_path, &new_path)
+      .map_err(|e| TreeError::from_io(e, &old_path))?;
+
+    let old_parent = node_id.ancestors(&self.inner).nth(1);
+    if old_parent != Some(new_parent) {
+      node_id.detach(&mut self.inner);
+      new_parent.append(node_id, &mut self.inner);
+      for parent in old_parent.into_iter().chain([new_parent]) {
+        count_children(&mut self.inner, parent);
+      }
+    }
+
+    let new_depth = self.inner[new_parent].get().depth + 1;
+    let old_depth = self.inner[node_id].get().depth;
+    let ids: Vec<NodeId> = node_id.descendants(&self.inner).collect();
+    for id in ids {
+      let node = self.inner[id].get_mut();
+      let relative = node.path().strip_prefix(&old_path).unwrap_or(Path::new(""));
+      let path = if relative.as_os_str().is_empty() {
+        new_path.clone()
+      } else {
+        new_path.join(relative)
+      };
+
+      self.paths.remove(node.path());
+      self.paths.insert(path.clone(), id);
+      node.set_path(path);
+      node.depth = node.depth - old_depth + new_depth;
+      self.assign_uid(id);
+    }
+
+    // what is read of a file depends on its extension
+    let is_dir = self.inner[node_id].get().is_dir();
+    if !is_dir && old_path.extension() != new_path.extension() {
+      self.reindex_path(&new_path)?;
+    }
+    Ok(())
+  }
+
+  // The node of a live id
+  fn get_node(&self, node_id: NodeId) -> TreeResult<&Node> {
+    self
+      .inner
+      .get(node_id)
+      .filter(|node| !node.is_removed())
+      .map(|node| node.get())
+      .ok_or(TreeError::MissingNode(node_id))
+  }
+
+  // Unindexes the paths before dropping the nodes
+  fn remove_subtree(&mut self, node_id: NodeId) {
+    for id in node_id.descendants(&self.inner) {
+      self.paths.remove(self.inner[id].get().path());
+    }
+    let parent = node_id.ancestors(&self.inner).nth(1);
+    node_id.remove_subtree(&mut self.inner);
+    if let Some(parent) = parent {
+      count_children(&mut self.inner, parent);
+    }
+  }
+
+  // Drops directories without any file below, returns how many. The root
+  // is kept even if empty, as are directories not loaded yet.
+  pub fn prune_empty(&mut self) -> usize {
+    // children come before their parent in post-order
+    let post_order: Vec<NodeId> = self
+      .root
+      .traverse(&self.inner)
+      .filter_map(|edge| match edge {
+        NodeEdge::End(id) => Some(id),
+        NodeEdge::Start(_) => None,
+      })
+      .collect();
+
+    let mut pruned = 0;
+    for id in post_order {
+      let node = self.inner[id].get();
+      if id == self.root
+        || !node.is_dir()
+        || !node.is_loaded()
+        || id.children(&self.inner).next().is_some()
+      {
+        continue;
+      }
+      self.remove_subtree(id);
+      pruned += 1;
+    }
+    pruned
+  }
+}
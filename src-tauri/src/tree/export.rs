@@ -0,0 +1,202 @@
+use super::{
+  node::{Node, SerializableNode},
+  Tree, TreeError, TreeResult,
+};
+use indextree::NodeEdge;
+use std::path::Path;
+
+impl Tree {
+  // Converts the whole hierarchy into nested nodes which serde can emit.
+  pub fn to_serializable(&self) -> SerializableNode {
+    let inner = self.inner();
+    // nodes started but not ended yet, each below the one before
+    let mut open: Vec<SerializableNode> = Vec::new();
+
+    for edge in self.root.traverse(inner) {
+      match edge {
+        NodeEdge::Start(id) => {
+          let node = inner[id].get();
+          open.push(SerializableNode {
+            name: node.file_name_lossy().to_string(),
+            path: node.path().to_owned(),
+            is_dir: node.is_dir(),
+            children: Vec::new(),
+          });
+        }
+        NodeEdge::End(_) => {
+          let ended = open.pop().expect("ends follow starts");
+          match open.last_mut() {
+            Some(parent) => parent.children.push(ended),
+            None => return ended,
+          }
+        }
+      }
+    }
+    unreachable!("the root ends the traversal")
+  }
+
+  // Emits the complete hierarchy with metadata as JSON, depth-first and
+  // siblings in discovered order. Each node has all of its fields in
+  // declaration order, then its `children`.
+  pub fn to_json(&self) -> TreeResult<String> {
+    let mut json = String::new();
+
+    for edge in self.root.traverse(self.inner()) {
+      match edge {
+        NodeEdge::Start(id) => {
+          if json.ends_with('}') {
+            json.push(',');
+          }
+          let fields = node_fields(self.inner()[id].get())?;
+          json.push_str(&fields);
+          json.push_str(",\"children\":[");
+        }
+        NodeEdge::End(_) => json.push_str("]}"),
+      }
+    }
+
+    Ok(json)
+  }
+
+  // Renders the hierarchy as nested `-` bullets, two spaces per level and
+  // directories with a trailing `/`. Follows the current child order.
+  pub fn to_markdown_outline(&self) -> String {
+    let mut outline = String::new();
+    let mut level = 0;
+
+    for edge in self.root.traverse(self.inner()) {
+      match edge {
+        NodeEdge::Start(id) => {
+          let node = self.inner()[id].get();
+          let slash = if node.is_dir() { "/" } else { "" };
+          outline.push_str(&"  ".repeat(level));
+          outline.push_str(&format!("- {}{slash}\n", node.file_name_lossy()));
+          level += 1;
+        }
+        NodeEdge::End(_) => level -= 1,
+      }
+    }
+
+    outline
+  }
+
+  // Renders the hierarchy as nested `<ul>` lists, the root included. Files
+  // link to their path relative to the root, directories with children hold
+  // a nested list. Follows the current child order.
+  pub fn to_html(&self) -> String {
+    let inner = self.inner();
+    let mut html = String::from("<ul>\n");
+    let mut level = 1;
+
+    for edge in self.root.traverse(inner) {
+      match edge {
+        NodeEdge::Start(id) => {
+          let node = inner[id].get();
+          let indent = "  ".repeat(level);
+          let name = escape_html(&node.file_name_lossy());
+          if !node.is_dir() {
+            let href = self
+              .relative_path(id)
+              .map(|path| href_of(&path))
+              .unwrap_or_default();
+            html.push_str(&format!(
+              "{indent}<li><a href=\"{href}\">{name}</a></li>\n"
+            ));
+          } else if id.children(inner).next().is_some() {
+            html.push_str(&format!("{indent}<li>{name}\n{indent}  <ul>\n"));
+            level += 2;
+          } else {
+            html.push_str(&format!("{indent}<li>{name}</li>\n"));
+          }
+        }
+        NodeEdge::End(id) => {
+          if inner[id].get().is_dir() && id.children(inner).next().is_some() {
+            level -= 2;
+            let indent = "  ".repeat(level);
+            html.push_str(&format!("{indent}  </ul>\n{indent}</li>\n"));
+          }
+        }
+      }
+    }
+
+    html.push_str("</ul>\n");
+    html
+  }
+
+  // Renders the hierarchy like `tree(1)`, the root name comes first.
+  pub fn to_ascii(&self) -> String {
+    let inner = self.inner();
+    let mut ascii = String::new();
+    // per open level below the root, if its node is the last sibling
+    let mut last_flags: Vec<bool> = Vec::new();
+
+    for edge in self.root.traverse(inner) {
+      match edge {
+        NodeEdge::Start(id) if id == self.root => {
+          ascii.push_str(&inner[id].get().file_name_lossy());
+          ascii.push('\n');
+        }
+        NodeEdge::Start(id) => {
+          for is_last in &last_flags {
+            ascii.push_str(if *is_last { "    " } else { "│   " });
+          }
+          let is_last = inner[id].next_sibling().is_none();
+          ascii.push_str(if is_last { "└── " } else { "├── " });
+          ascii.push_str(&inner[id].get().file_name_lossy());
+          ascii.push('\n');
+          last_flags.push(is_last);
+        }
+        NodeEdge::End(id) if id == self.root => {}
+        NodeEdge::End(_) => {
+          last_flags.pop();
+        }
+      }
+    }
+
+    ascii
+  }
+}
+
+// The fields of a node as an unclosed JSON object
+fn node_fields(node: &Node) -> TreeResult<String> {
+  let mut fields = serde_json::to_string(node).map_err(TreeError::Json)?;
+  // an object which always has fields
+  fields.pop();
+  Ok(fields)
+}
+
+// Escapes text for HTML content and attribute values
+fn escape_html(text: &str) -> String {
+  let mut escaped = String::with_capacity(text.len());
+  for c in text.chars() {
+    match c {
+      '&' => escaped.push_str("&amp;"),
+      '<' => escaped.push_str("&lt;"),
+      '>' => escaped.push_str("&gt;"),
+      '"' => escaped.push_str("&quot;"),
+      '\'' => escaped.push_str("&#39;"),
+      c => escaped.push(c),
+    }
+  }
+  escaped
+}
+
+// URL of a relative path, `/` separated and percent-encoded apart from
+// unreserved chars
+fn href_of(path: &Path) -> String {
+  let joined = path
+    .components()
+    .map(|c| c.as_os_str().to_string_lossy())
+    .collect::<Vec<_>>()
+    .join("/");
+
+  let mut href = String::with_capacity(joined.len());
+  for byte in joined.bytes() {
+    if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+      href.push(byte as char);
+    } else {
+      href.push_str(&format!("%{byte:02X}"));
+    }
+  }
+  href
+}
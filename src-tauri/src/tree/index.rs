@@ -0,0 +1,66 @@
+use super::{node::hash_file, Tree};
+use indextree::NodeId;
+use std::{collections::HashMap, path::PathBuf};
+
+impl Tree {
+  // Maps each inline tag to the notes carrying it, in traversal order.
+  //
+  // Empty unless the tree was built with content read.
+  pub fn tag_index(&self) -> HashMap<String, Vec<PathBuf>> {
+    let mut index: HashMap<String, Vec<PathBuf>> = HashMap::new();
+    for node in self.iter() {
+      for tag in &node.tags {
+        index
+          .entry(tag.clone())
+          .or_default()
+          .push(node.path().to_path_buf());
+      }
+    }
+    index
+  }
+
+  // Groups of files with identical bytes, at least two per group. Only
+  // files sharing their size get hashed, streamed from disk unless hashed
+  // already; empty files and symlinks are left out. Groups are ordered by
+  // their first path.
+  pub fn find_duplicates(&self) -> Vec<Vec<NodeId>> {
+    let mut by_size: HashMap<u64, Vec<NodeId>> = HashMap::new();
+    for id in self.root.descendants(&self.inner) {
+      let node = self.inner[id].get();
+      if !node.is_dir() && !node.is_symlink() && node.size > 0 {
+        by_size.entry(node.size).or_default().push(id);
+      }
+    }
+
+    let mut groups: Vec<Vec<NodeId>> = Vec::new();
+    for ids in by_size.into_values().filter(|ids| ids.len() > 1) {
+      let mut by_hash: HashMap<u64, Vec<NodeId>> = HashMap::new();
+      for id in ids {
+        let node = self.inner[id].get();
+        let hash = match node.content_hash {
+          Some(hash) => hash,
+          // unreadable files can't be compared
+          None => match hash_file(node.path()) {
+            Ok(hash) => hash,
+            Err(_) => continue,
+          },
+        };
+        by_hash.entry(hash).or_default().push(id);
+      }
+      groups.extend(by_hash.into_values().filter(|ids| ids.len() > 1));
+    }
+
+    for group in groups.iter_mut() {
+      group.sort_by(|a, b| {
+        self.inner[*a].get().path().cmp(self.inner[*b].get().path())
+      });
+    }
+    groups.sort_by(|a, b| {
+      self.inner[a[0]]
+        .get()
+        .path()
+        .cmp(self.inner[b[0]].get().path())
+    });
+    groups
+  }
+}
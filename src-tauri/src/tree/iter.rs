@@ -0,0 +1,67 @@
+use super::{node::Node, Tree};
+use indextree::{Arena, Descendants, NodeId};
+use std::{collections::VecDeque, path::PathBuf};
+
+/// Depth-first pre-order iterator, a directory comes before its children.
+pub struct Iter<'a> {
+  inner: &'a Arena<Node>,
+  descendants: Descendants<'a, Node>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+  type Item = &'a Node;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let inner = self.inner;
+    self.descendants.next().map(|id| inner[id].get())
+  }
+}
+
+/// Breadth-first iterator, siblings keep the discovered order.
+pub struct BfsIter<'a> {
+  inner: &'a Arena<Node>,
+  queue: VecDeque<NodeId>,
+}
+
+impl<'a> Iterator for BfsIter<'a> {
+  type Item = &'a Node;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let inner = self.inner;
+    let node_id = self.queue.pop_front()?;
+    self.queue.extend(node_id.children(inner));
+    Some(inner[node_id].get())
+  }
+}
+
+impl Tree {
+  // Lazily walks all nodes depth-first, starting with the root.
+  pub fn iter(&self) -> Iter<'_> {
+    Iter {
+      inner: self.inner(),
+      descendants: self.root.descendants(self.inner()),
+    }
+  }
+
+  // Walks all nodes level by level, starting with the root.
+  pub fn iter_bfs(&self) -> BfsIter<'_> {
+    BfsIter {
+      inner: self.inner(),
+      queue: VecDeque::from([self.root]),
+    }
+  }
+
+  // Paths of all files, depth-first in child order.
+  pub fn flatten(&self) -> Vec<PathBuf> {
+    self
+      .iter()
+      .filter(|node| !node.is_dir())
+      .map(|node| node.path().to_owned())
+      .collect()
+  }
+
+  // Paths of all nodes, the root included, depth-first in child order.
+  pub fn flatten_all(&self) -> Vec<PathBuf> {
+    self.iter().map(|node| node.path().to_owned()).collect()
+  }
+}
@@ -0,0 +1,188 @@
+use super::{content, options::is_markdown};
+use infer::MatcherType;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, path::Path};
+
+/// Category of a node, drives the icon shown by the frontend.
+#[derive(
+  Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub enum FileKind {
+  Directory,
+  Markdown,
+  Text,
+  Image,
+  Audio,
+  Video,
+  Pdf,
+  #[default]
+  Other,
+}
+
+// Lowercase extensions per kind, markdown ones are [MARKDOWN_EXTENSIONS].
+//
+// [MARKDOWN_EXTENSIONS]: super::options::MARKDOWN_EXTENSIONS
+const KIND_BY_EXTENSION: &[(FileKind, &[&str])] = &[
+  (
+    FileKind::Text,
+    &[
+      "txt", "text", "org", "rst", "adoc", "csv", "tsv", "json", "yaml", "yml",
+      "toml", "xml", "html", "htm", "css", "js", "ts", "rs", "py", "sh", "log",
+    ],
+  ),
+  (
+    FileKind::Image,
+    &[
+      "png", "jpg", "jpeg", "gif", "webp", "svg", "bmp", "ico", "tif", "tiff",
+      "avif", "heic",
+    ],
+  ),
+  (
+    FileKind::Audio,
+    &["mp3", "wav", "ogg", "oga", "flac", "m4a", "aac", "opus"],
+  ),
+  (
+    FileKind::Video,
+    &["mp4", "m4v", "webm", "mov", "mkv", "avi", "ogv"],
+  ),
+  (FileKind::Pdf, &["pdf"]),
+];
+
+// Emoji hint per kind for the frontend, [DEFAULT_ICON] for the rest
+const ICON_BY_KIND: &[(FileKind, &str)] = &[
+  (FileKind::Directory, "📁"),
+  (FileKind::Markdown, "📝"),
+  (FileKind::Image, "🖼"),
+  (FileKind::Audio, "🎵"),
+];
+
+/// Icon of the kinds without one of their own.
+pub const DEFAULT_ICON: &str = "📄";
+
+impl FileKind {
+  pub fn icon(self) -> &'static str {
+    ICON_BY_KIND
+      .iter()
+      .find(|(kind, _)| *kind == self)
+      .map_or(DEFAULT_ICON, |(_, icon)| *icon)
+  }
+
+  // Icon from `overrides` if there, else the built-in one
+  pub fn icon_with(self, overrides: &HashMap<FileKind, String>) -> &str {
+    overrides.get(&self).map_or(self.icon(), String::as_str)
+  }
+
+  // Kind of a file by its extension
+  pub fn from_path(path: &Path) -> Self {
+    if is_markdown(path) {
+      return FileKind::Markdown;
+    }
+
+    let ext = match path.extension().and_then(|ext| ext.to_str()) {
+      Some(ext) => ext.to_lowercase(),
+      None => return FileKind::Other,
+    };
+    KIND_BY_EXTENSION
+      .iter()
+      .find(|(_, exts)| exts.contains(&ext.as_str()))
+      .map_or(FileKind::Other, |(kind, _)| *kind)
+  }
+
+  // If the extension may not tell the truth, e.g. a misnamed image
+  pub fn is_ambiguous(self) -> bool {
+    matches!(self, FileKind::Other | FileKind::Text)
+  }
+
+  // Kind of a file by its magic bytes, None if they don't tell. Text
+  // without an extension is taken for markdown if it has a frontmatter or
+  // a heading.
+  pub fn from_head(head: &[u8], has_extension: bool) -> Option<Self> {
+    if let Some(kind) = infer::get(head) {
+      return match kind.matcher_type() {
+        MatcherType::Image => Some(FileKind::Image),
+        MatcherType::Audio => Some(FileKind::Audio),
+        MatcherType::Video => Some(FileKind::Video),
+        _ if kind.mime_type() == "application/pdf" => Some(FileKind::Pdf),
+        _ => None,
+      };
+    }
+
+    if has_extension || looks_binary(head) {
+      return None;
+    }
+    let text = String::from_utf8_lossy(head);
+    let is_markdown = content::frontmatter_block(&text).is_some()
+      || !content::headings(&text).is_empty();
+    Some(if is_markdown {
+      FileKind::Markdown
+    } else {
+      FileKind::Text
+    })
+  }
+}
+
+/// Bytes looked at to tell the kind of a file by its content.
+pub const MAGIC_BYTES: usize = 512;
+
+/// Bytes looked at to tell binary from text.
+pub const SNIFF_BYTES: usize = 8 * 1024;
+
+// Binary if the head has NUL bytes, unless it is UTF-16, or if it is no
+// UTF-8 and has control chars. Invalid UTF-8 alone may be legacy 8-bit text.
+pub fn looks_binary(head: &[u8]) -> bool {
+  if utf16_endian(head).is_some() {
+    return false;
+  }
+  if head.contains(&0) {
+    return true;
+  }
+
+  match std::str::from_utf8(head) {
+    Ok(_) => false,
+    // a char cut off at the end of the head
+    Err(e) if e.error_len().is_none() => false,
+    Err(_) => head
+      .iter()
+      .any(|b| b.is_ascii_control() && !b"\t\n\r\x0c\x1b".contains(b)),
+  }
+}
+
+// If the head is UTF-16 by BOM, or by NUL bytes at only odd or only even
+// offsets; true for little endian
+pub(crate) fn utf16_endian(head: &[u8]) -> Option<bool> {
+  let (little_endian, units) = match head {
+    [0xff, 0xfe, rest @ ..] => (true, rest),
+    [0xfe, 0xff, rest @ ..] => (false, rest),
+    _ => {
+      let nul_at = |parity: usize| {
+        head
+          .iter()
+          .skip(parity)
+          .step_by(2)
+          .filter(|b| **b == 0)
+          .count()
+      };
+      match (nul_at(0), nul_at(1)) {
+        (0, odd) if odd > 0 => (true, head),
+        (even, 0) if even > 0 => (false, head),
+        _ => return None,
+      }
+    }
+  };
+
+  let units = units.chunks_exact(2).map(|pair| {
+    if little_endian {
+      u16::from_le_bytes([pair[0], pair[1]])
+    } else {
+      u16::from_be_bytes([pair[0], pair[1]])
+    }
+  });
+  let decoded: Vec<_> = char::decode_utf16(units).collect();
+  // a surrogate pair cut off at the end of the head
+  let complete = match decoded.last() {
+    Some(Err(_)) => &decoded[..decoded.len() - 1],
+    _ => &decoded[..],
+  };
+  let is_text = complete.iter().all(|c| matches!(c, Ok(c) if *c != '\0'));
+  is_text.then_some(little_endian)
+}
@@ -0,0 +1,336 @@
+use super::{content, options::is_note, Tree};
+use indextree::NodeId;
+use serde::Serialize;
+use std::{
+  collections::{BTreeSet, HashMap, HashSet},
+  path::{Component, Path, PathBuf},
+};
+
+/// Notes and the links between them, for a graph view. Paths are relative
+/// to the root.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct LinkGraph {
+  // one per file, in traversal order
+  pub nodes: Vec<GraphNode>,
+  // sorted, links pointing nowhere and to the note itself are dropped
+  pub edges: Vec<GraphEdge>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GraphNode {
+  pub path: PathBuf,
+  pub title: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct GraphEdge {
+  pub from: PathBuf,
+  pub to: PathBuf,
+}
+
+impl Tree {
+  // Maps each note to the notes linking to it with a `[[wikilink]]`.
+  //
+  // Notes without incoming links are left out, see `unresolved_links` for
+  // links pointing nowhere.
+  pub fn backlinks(&self) -> HashMap<PathBuf, Vec<PathBuf>> {
+    self.wikilink_pass().0
+  }
+
+  // Pairs each note with the `[[wikilink]]` targets matching no file.
+  pub fn unresolved_links(&self) -> Vec<(PathBuf, String)> {
+    self.wikilink_pass().1
+  }
+
+  // Maps each note to the `http(s)://` URLs in its loaded content, each URL
+  // once. Notes without any are left out.
+  pub fn external_urls(&self) -> HashMap<PathBuf, Vec<String>> {
+    self
+      .iter()
+      .filter_map(|node| {
+        let urls = content::external_urls(node.file_text.as_deref()?);
+        (!urls.is_empty()).then(|| (node.path().to_owned(), urls))
+      })
+      .collect()
+  }
+
+  // Builds the graph of all files and their `[[wikilinks]]` and relative
+  // markdown links, see `resolve_link` and `resolve_md_link`.
+  pub fn link_graph(&self) -> LinkGraph {
+    let mut graph = LinkGraph::default();
+    for id in self.root.descendants(&self.inner) {
+      let node = self.inner[id].get();
+      if node.is_dir() {
+        continue;
+      }
+      if let Some(path) = self.relative_path(id) {
+        graph.nodes.push(GraphNode {
+          path,
+          title: node.title(),
+        });
+      }
+    }
+
+    let edges: BTreeSet<GraphEdge> = self
+      .resolved_links()
+      .into_iter()
+      .filter_map(|(from, to)| {
+        Some(GraphEdge {
+          from: self.relative_path(from)?,
+          to: self.relative_path(to)?,
+        })
+      })
+      .collect();
+    graph.edges = edges.into_iter().collect();
+    graph
+  }
+
+  // Counts the notes linking to each file, by `[[wikilink]]` or markdown
+  // link. A note linking twice counts once, links to itself not at all.
+  // Files without incoming links are left out.
+  pub fn backlink_counts(&self) -> HashMap<PathBuf, usize> {
+    let pairs: HashSet<(NodeId, NodeId)> =
+      self.resolved_links().into_iter().collect();
+    let mut counts: HashMap<PathBuf, usize> = HashMap::new();
+    for (_, to) in pairs {
+      *counts
+        .entry(self.inner[to].get().path().to_owned())
+        .or_default() += 1;
+    }
+    counts
+  }
+
+  // Notes no other note links to, by `[[wikilink]]` or markdown link,
+  // sorted by path. A note linking only to itself is one too.
+  pub fn orphans(&self) -> Vec<NodeId> {
+    let linked: HashSet<NodeId> = self
+      .resolved_links()
+      .into_iter()
+      .map(|(_, to)| to)
+      .collect();
+
+    let mut orphans: Vec<NodeId> = self
+      .root
+      .descendants(&self.inner)
+      .filter(|id| {
+        let node = self.inner[*id].get();
+        !node.is_dir() && is_note(node.path()) && !linked.contains(id)
+      })
+      .collect();
+    orphans
+      .sort_by(|a, b| self.inner[*a].get().path().cmp(self.inner[*b].get().path()));
+    orphans
+  }
+
+  // (from, to) of all wikilinks and markdown links which resolve to another
+  // file, a pair as often as it's linked
+  fn resolved_links(&self) -> Vec<(NodeId, NodeId)> {
+    let names = self.name_index();
+    let mut links = Vec::new();
+
+    for from in self.root.descendants(&self.inner) {
+      let node = self.inner[from].get();
+      let wikilinks = node
+        .links
+        .iter()
+        .filter_map(|target| self.resolve_with(&names, from, target));
+      let md_links = node
+        .md_links
+        .iter()
+        .filter_map(|target| self.resolve_md_link(from, target));
+      links.extend(
+        wikilinks
+          .chain(md_links)
+          .filter(|to| *to != from)
+          .map(|to| (from, to)),
+      );
+    }
+
+    links
+  }
+
+  // Finds the file a relative markdown link of `from` points to, e.g.
+  // `../other/note.md#part`. Leading `/` is the root. None for URLs and
+  // anything not in the tree.
+  pub fn resolve_md_link(&self, from: NodeId, target: &str) -> Option<NodeId> {
+    let target = link_path(target)?;
+
+    let base = if target.starts_with('/') {
+      self.inner[self.top_dir(from)?].get().path()
+    } else {
+      self.inner.get(from)?.get().parent_path()?
+    };
+    let mut path = base.to_path_buf();
+    for component in Path::new(target.trim_start_matches('/')).components() {
+      match component {
+        Component::Normal(name) => path.push(name),
+        Component::ParentDir => {
+          path.pop();
+        }
+        _ => {}
+      }
+    }
+
+    self
+      .paths
+      .get(&path)
+      .copied()
+      .filter(|id| !self.inner[*id].get().is_dir())
+  }
+
+  // Pairs each note with the `[[wikilink]]` targets matching no file and the
+  // relative markdown links to no file in the tree, sorted by note. URLs
+  // aren't checked, links to folders count as broken.
+  pub fn broken_links(&self) -> Vec<(PathBuf, String)> {
+    let mut broken = self.unresolved_links();
+    for id in self.root.descendants(&self.inner) {
+      let node = self.inner[id].get();
+      let dead = node.md_links.iter().filter(|target| {
+        link_path(target).is_some() && self.resolve_md_link(id, target).is_none()
+      });
+      broken.extend(dead.map(|target| (node.path().to_owned(), target.clone())));
+    }
+
+    broken.sort_by(|a, b| a.0.cmp(&b.0));
+    broken
+  }
+
+  fn wikilink_pass(
+    &self,
+  ) -> (HashMap<PathBuf, Vec<PathBuf>>, Vec<(PathBuf, String)>) {
+    let names = self.name_index();
+    let mut backlinks: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+    let mut unresolved = Vec::new();
+
+    for from in self.root.descendants(&self.inner) {
+      let node = self.inner[from].get();
+      for target in &node.links {
+        match self.resolve_with(&names, from, target) {
+          Some(to) => {
+            let sources = backlinks
+              .entry(self.inner[to].get().path().into())
+              .or_default();
+            if !sources.iter().any(|s| s == node.path()) {
+              sources.push(node.path().into());
+            }
+          }
+          None => unresolved.push((node.path().into(), target.clone())),
+        }
+      }
+    }
+
+    (backlinks, unresolved)
+  }
+
+  // Files keyed by lowercased file name, notes by their stem too
+  fn name_index(&self) -> HashMap<String, Vec<NodeId>> {
+    let mut names: HashMap<String, Vec<NodeId>> = HashMap::new();
+    for id in self.root.descendants(&self.inner) {
+      let node = self.inner[id].get();
+      if node.is_dir() {
+        continue;
+      }
+      let path = node.path();
+      names
+        .entry(node.file_name_lossy().to_lowercase())
+        .or_default()
+        .push(id);
+      if is_note(path) {
+        if let Some(stem) = path.file_stem() {
+          names
+            .entry(stem.to_string_lossy().to_lowercase())
+            .or_default()
+            .push(id);
+        }
+      }
+    }
+    names
+  }
+
+  // Finds the file a `[[wikilink]]` of `from` points to, see `resolve_with`.
+  // Indexes the whole tree per call, `backlinks` and friends resolve in bulk.
+  pub fn resolve_link(&self, from: NodeId, target: &str) -> Option<NodeId> {
+    self.inner.get(from)?;
+    self.resolve_with(&self.name_index(), from, target)
+  }
+
+  // Matches the last component of `target` by name or note stem, ignoring
+  // case. Leading folders of `target` have to match the parent folders of
+  // the file. A match next to `from` wins, then one in the nearest folder
+  // above, then the one with the fewest components and the path sorting
+  // first.
+  fn resolve_with(
+    &self,
+    names: &HashMap<String, Vec<NodeId>>,
+    from: NodeId,
+    target: &str,
+  ) -> Option<NodeId> {
+    let target = target.trim_matches('/').to_lowercase();
+    let (dirs, name) = match target.rsplit_once('/') {
+      Some((dirs, name)) => (Some(dirs), name),
+      None => (None, target.as_str()),
+    };
+    let from_dir = self.inner[from].get().parent_path();
+
+    names
+      .get(name)?
+      .iter()
+      .copied()
+      .filter(|id| match dirs {
+        Some(dirs) => self.inner[*id].get().parent_path().is_some_and(|p| {
+          Path::new(&p.to_string_lossy().to_lowercase()).ends_with(dirs)
+        }),
+        None => true,
+      })
+      .min_by_key(|id| {
+        let path = self.inner[*id].get().path();
+        // folders up from `from` to the match, none if it's elsewhere
+        let levels_up = match (from_dir, path.parent()) {
+          (Some(from_dir), Some(dir)) if from_dir.starts_with(dir) => {
+            depth_of(from_dir) - depth_of(dir)
+          }
+          _ => usize::MAX,
+        };
+        (levels_up, depth_of(path), path.to_path_buf())
+      })
+  }
+}
+
+// The path part of a markdown link target, None for URLs and anchors into
+// the note itself
+fn link_path(target: &str) -> Option<String> {
+  let target = target.split(['#', '?']).next().unwrap_or_default();
+  if target.is_empty() || target.contains(':') {
+    return None;
+  }
+  Some(percent_decode(target))
+}
+
+// `%20` and friends, invalid escapes are kept as they are
+fn percent_decode(text: &str) -> String {
+  let bytes = text.as_bytes();
+  let mut decoded = Vec::with_capacity(bytes.len());
+  let mut idx = 0;
+  while idx < bytes.len() {
+    let escaped = bytes
+      .get(idx + 1..idx + 3)
+      .filter(|_| bytes[idx] == b'%')
+      .and_then(|hex| std::str::from_utf8(hex).ok())
+      .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+    match escaped {
+      Some(byte) => {
+        decoded.push(byte);
+        idx += 3;
+      }
+      None => {
+        decoded.push(bytes[idx]);
+        idx += 1;
+      }
+    }
+  }
+  String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn depth_of(path: &Path) -> usize {
+  path.components().count()
+}
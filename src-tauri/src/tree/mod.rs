@@ -1,50 +1,485 @@
 use chrono::{SecondsFormat, TimeZone, Utc};
-use crossbeam::channel::{self, Sender};
+use crossbeam::channel::{self, Receiver, Sender};
 use ignore::{WalkBuilder, WalkParallel};
 use indextree::{Arena, NodeId};
 use std::{
+  borrow::Cow,
   collections::HashMap,
   convert::From,
-  fs,
+  error::Error,
+  fmt, fs, io,
   num::NonZeroUsize,
   path::{Path, PathBuf},
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+  },
   thread::{self, available_parallelism},
-  time::UNIX_EPOCH,
+  time::{Instant, UNIX_EPOCH},
 };
 
-use node::Node;
-use visitor::{BranchVisitorBuilder, TraversalState};
+use node::{hash_bytes, ContentReader, Node};
+use options::{EntryFilter, ReadMode, WalkOptions};
+use stats::TraversalStats;
+use visitor::{
+  BranchVisitorBuilder, EntryError, NodeBudget, ReadGate, TraversalState,
+};
 
-use crate::json::{NoteData, NoteTree, NoteTreeItem, NotesData};
+use crate::{
+  json::{NoteData, NoteTree, NoteTreeItem, NotesData},
+  paths::{case_key, strip_verbatim},
+};
 
 use self::node::from_node;
 
+pub mod builder;
+pub mod cache;
+pub mod content;
+pub mod diff;
+pub mod edit;
+pub mod export;
+pub mod index;
+pub mod iter;
+pub mod kind;
+pub mod links;
 pub mod node;
+pub mod options;
+pub mod search;
+pub mod sort;
+pub mod stats;
 pub mod visitor;
+pub mod watch;
+
+/// Nodes between two progress reports of `Tree::init_with_progress`.
+pub const PROGRESS_INTERVAL: usize = 256;
 
 /// Virtual data structure that represents file hierarchy.
 #[derive(Debug)]
 pub struct Tree {
   inner: Arena<Node>,
   pub root: NodeId,
+  // how content is read for nodes added later
+  read_ctn: ReadMode,
+  // canonical path to node, kept in sync with `inner`
+  paths: PathIndex,
+  // the walk stopped at `WalkOptions::max_nodes`
+  truncated: bool,
+  // of the traversal, for walks of `expand`
+  opts: WalkOptions,
+  // depth limit of the traversal, below the root
+  depth: Option<usize>,
+  // see `Tree::traversal_stats`
+  traversal_stats: Option<TraversalStats>,
+}
+
+// Node by canonical path, compared as the OS does, see [case_key]
+#[derive(Debug, Default)]
+struct PathIndex(HashMap<PathBuf, NodeId>);
+
+impl PathIndex {
+  fn get(&self, path: &Path) -> Option<&NodeId> {
+    self.0.get(case_key(path).as_ref())
+  }
+
+  fn contains_key(&self, path: &Path) -> bool {
+    self.0.contains_key(case_key(path).as_ref())
+  }
+
+  fn insert(&mut self, path: PathBuf, node_id: NodeId) -> Option<NodeId> {
+    let key = match case_key(&path) {
+      Cow::Owned(key) => key,
+      Cow::Borrowed(_) => path,
+    };
+    self.0.insert(key, node_id)
+  }
+
+  fn remove(&mut self, path: &Path) -> Option<NodeId> {
+    self.0.remove(case_key(path).as_ref())
+  }
+}
+
+impl FromIterator<(PathBuf, NodeId)> for PathIndex {
+  fn from_iter<I: IntoIterator<Item = (PathBuf, NodeId)>>(iter: I) -> Self {
+    let mut index = Self::default();
+    for (path, node_id) in iter {
+      index.insert(path, node_id);
+    }
+    index
+  }
+}
+
+/// Errors surfaced while building or querying a [Tree].
+#[derive(Debug)]
+pub enum TreeError {
+  NotFound(PathBuf),
+  PermissionDenied(PathBuf),
+  MissingRoot,
+  ExpectedParent(PathBuf),
+  InvalidGlob(String),
+  InvalidRegex(regex::Error),
+  MissingMetadata(PathBuf),
+  // a traversal thread panicked, e.g. in a callback
+  TraversalPanicked,
+  // with what was found until then
+  Cancelled(Box<Tree>),
+  AlreadyExists(PathBuf),
+  InvalidName(String),
+  // an entry or its content, with the reason
+  Unreadable(PathBuf, String),
+  // the id isn't in the arena (anymore)
+  MissingNode(NodeId),
+  Json(serde_json::Error),
+  Watch(notify::Error),
+  Trash(trash::Error),
+  Io(io::Error),
+}
+
+pub type TreeResult<T> = Result<T, TreeError>;
+
+impl TreeError {
+  // Keeps the offending path for the io errors the frontend tells apart
+  fn from_io(err: io::Error, path: &Path) -> Self {
+    match err.kind() {
+      io::ErrorKind::NotFound => TreeError::NotFound(path.to_path_buf()),
+      io::ErrorKind::AlreadyExists => TreeError::AlreadyExists(path.to_path_buf()),
+      io::ErrorKind::PermissionDenied => {
+        TreeError::PermissionDenied(path.to_path_buf())
+      }
+      _ => TreeError::Io(err),
+    }
+  }
+}
+
+impl fmt::Display for TreeError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      TreeError::NotFound(path) => write!(f, "Not Found: {}", path.display()),
+      TreeError::PermissionDenied(path) => {
+        write!(f, "Permission Denied: {}", path.display())
+      }
+      TreeError::MissingRoot => write!(f, "MissingRoot"),
+      TreeError::ExpectedParent(path) => {
+        write!(f, "ExpectedParent: {}", path.display())
+      }
+      TreeError::InvalidGlob(e) => write!(f, "Invalid Glob: {e}"),
+      TreeError::InvalidRegex(e) => write!(f, "Invalid Regex: {e}"),
+      TreeError::MissingMetadata(path) => {
+        write!(f, "Missing Metadata: {}", path.display())
+      }
+      TreeError::TraversalPanicked => write!(f, "Traversal Panicked"),
+      TreeError::Cancelled(_) => write!(f, "Cancelled"),
+      TreeError::AlreadyExists(path) => {
+        write!(f, "Already Exists: {}", path.display())
+      }
+      TreeError::InvalidName(name) => write!(f, "Invalid Name: {name}"),
+      TreeError::Unreadable(path, reason) => {
+        write!(f, "Unreadable: {}: {reason}", path.display())
+      }
+      TreeError::MissingNode(id) => write!(f, "Missing Node: {id:?}"),
+      TreeError::Json(e) => write!(f, "{e}"),
+      TreeError::Watch(e) => write!(f, "{e}"),
+      TreeError::Trash(e) => write!(f, "{e}"),
+      TreeError::Io(e) => write!(f, "{e}"),
+    }
+  }
+}
+
+impl Error for TreeError {
+  fn source(&self) -> Option<&(dyn Error + 'static)> {
+    match self {
+      TreeError::InvalidRegex(e) => Some(e),
+      TreeError::Json(e) => Some(e),
+      TreeError::Watch(e) => Some(e),
+      TreeError::Trash(e) => Some(e),
+      TreeError::Io(e) => Some(e),
+      _ => None,
+    }
+  }
 }
 
-pub type TreeResult<T> = Result<T, String>;
+impl From<EntryError> for TreeError {
+  fn from(err: EntryError) -> Self {
+    match err.kind {
+      Some(io::ErrorKind::NotFound) => TreeError::NotFound(err.path),
+      Some(io::ErrorKind::PermissionDenied) => TreeError::PermissionDenied(err.path),
+      _ => TreeError::Unreadable(err.path, err.reason),
+    }
+  }
+}
+
+impl From<io::Error> for TreeError {
+  fn from(err: io::Error) -> Self {
+    TreeError::Io(err)
+  }
+}
 
 impl Tree {
   /// Constructor for [Tree].
   pub fn new(inner: Arena<Node>, root: NodeId) -> Self {
-    Self { inner, root }
+    let paths = root
+      .descendants(&inner)
+      .filter(|id| !inner[*id].get().is_virtual())
+      .map(|id| (inner[id].get().path().to_owned(), id))
+      .collect();
+
+    let mut tree = Self {
+      inner,
+      root,
+      read_ctn: ReadMode::None,
+      paths,
+      truncated: false,
+      opts: WalkOptions::default(),
+      depth: None,
+      traversal_stats: None,
+    };
+    let ids: Vec<NodeId> = root.descendants(&tree.inner).collect();
+    for id in ids {
+      tree.assign_uid(id);
+    }
+    tree
+  }
+
+  // Derives `Node::uid` from the relative path, after the node was placed
+  fn assign_uid(&mut self, node_id: NodeId) {
+    if let Some(relative) = self.relative_path(node_id) {
+      self.inner[node_id].get_mut().set_uid(path_uid(&relative));
+    }
   }
 
   // Initiates file system traversal and Tree construction.
   //　dir: root dir;
   // depth: maximum depth to recurse, None as no restriction;
-  // read_ctn: if read the content of file
-  pub fn init(dir: &str, depth: Option<usize>, read_ctn: bool) -> TreeResult<Self> {
-    let (inner, root) = Self::traverse(dir, depth, read_ctn)?;
+  // read_ctn: how much of the files to read, a bool means all or nothing;
+  // opts: walker options, see [WalkOptions]
+  pub fn init(
+    dir: &str,
+    depth: Option<usize>,
+    read_ctn: impl Into<ReadMode>,
+    opts: &WalkOptions,
+  ) -> TreeResult<Self> {
+    let hooks = TraverseHooks::default();
+    Self::init_with_hooks(dir, depth, read_ctn.into(), opts, &hooks)
+      .map(|(tree, _)| tree)
+  }
+
+  // Same as `init` without blocking the executor, the walk runs on the
+  // blocking thread pool of tokio.
+  #[cfg(feature = "async")]
+  pub async fn init_async(
+    dir: &str,
+    depth: Option<usize>,
+    read_ctn: impl Into<ReadMode>,
+    opts: &WalkOptions,
+  ) -> TreeResult<Self> {
+    let dir = dir.to_owned();
+    let read_ctn = read_ctn.into();
+    let opts = opts.clone();
+
+    tokio::task::spawn_blocking(move || Self::init(&dir, depth, read_ctn, &opts))
+      .await
+      .map_err(|_| TreeError::TraversalPanicked)?
+  }
+
+  // Same as `init`, also returning the entries which couldn't be read along
+  // with the reason. An unreadable directory is kept without children.
+  pub fn init_with_errors(
+    dir: &str,
+    depth: Option<usize>,
+    read_ctn: impl Into<ReadMode>,
+    opts: &WalkOptions,
+  ) -> TreeResult<(Self, Vec<(PathBuf, String)>)> {
+    let hooks = TraverseHooks::default();
+    let (tree, errors) =
+      Self::init_with_hooks(dir, depth, read_ctn.into(), opts, &hooks)?;
+    let errors = errors.into_iter().map(|e| (e.path, e.reason)).collect();
+    Ok((tree, errors))
+  }
+
+  // Best effort `init`: entries the walker couldn't read and files whose
+  // content couldn't be read are returned as errors next to the tree of
+  // everything else. Fails only if there is no tree at all.
+  pub fn init_lenient(
+    dir: &str,
+    depth: Option<usize>,
+    read_ctn: impl Into<ReadMode>,
+    opts: &WalkOptions,
+  ) -> TreeResult<(Self, Vec<TreeError>)> {
+    let hooks = TraverseHooks::default();
+    let (tree, errors) =
+      Self::init_with_hooks(dir, depth, read_ctn.into(), opts, &hooks)?;
+
+    let mut errors: Vec<TreeError> =
+      errors.into_iter().map(TreeError::from).collect();
+    errors.extend(tree.iter().filter_map(|node| {
+      let reason = node.read_error.clone()?;
+      Some(TreeError::Unreadable(node.path().to_owned(), reason))
+    }));
+    Ok((tree, errors))
+  }
+
+  // Same as `init`, stopping the walkers once `cancel` is set. A partial
+  // tree is handed back by [TreeError::Cancelled] then.
+  pub fn init_cancellable(
+    dir: &str,
+    depth: Option<usize>,
+    read_ctn: impl Into<ReadMode>,
+    opts: &WalkOptions,
+    cancel: Arc<AtomicBool>,
+  ) -> TreeResult<Self> {
+    let hooks = TraverseHooks {
+      cancel: Some(cancel),
+      ..TraverseHooks::default()
+    };
+    Self::init_with_hooks(dir, depth, read_ctn.into(), opts, &hooks)
+      .map(|(tree, _)| tree)
+  }
+
+  // Same as `init`, calling back with the count of nodes found so far.
+  //
+  // The callback runs on the collector thread, roughly every
+  // [PROGRESS_INTERVAL] nodes and once more with the final count, so don't
+  // expect a call per file.
+  pub fn init_with_progress<F>(
+    dir: &str,
+    depth: Option<usize>,
+    read_ctn: impl Into<ReadMode>,
+    opts: &WalkOptions,
+    progress: F,
+  ) -> TreeResult<Self>
+  where
+    F: Fn(usize) + Sync,
+  {
+    let hooks = TraverseHooks {
+      progress: Some(&progress),
+      ..TraverseHooks::default()
+    };
+    Self::init_with_hooks(dir, depth, read_ctn.into(), opts, &hooks)
+      .map(|(tree, _)| tree)
+  }
+
+  fn init_with_hooks(
+    dir: &str,
+    depth: Option<usize>,
+    read_ctn: ReadMode,
+    opts: &WalkOptions,
+    hooks: &TraverseHooks,
+  ) -> TreeResult<(Self, Vec<EntryError>)> {
+    let started = Instant::now();
+    let Traversal {
+      inner,
+      root,
+      errors,
+      truncated,
+      cancelled,
+    } = Self::traverse(Path::new(dir), depth, read_ctn, opts, None, hooks)?;
+
+    let mut tree = Self::new(inner, root);
+    tree.read_ctn = read_ctn;
+    tree.truncated = truncated;
+    tree.opts = opts.clone();
+    tree.depth = depth;
+    if opts.prune_empty_dirs {
+      tree.prune_empty();
+    }
+    let threads_used = threads_num(opts.threads);
+    tree.traversal_stats =
+      Some(tree.collect_traversal_stats(started.elapsed(), threads_used));
+    if cancelled {
+      return Err(TreeError::Cancelled(Box::new(tree)));
+    }
+    Ok((tree, errors))
+  }
 
-    Ok(Self::new(inner, root))
+  // Initiates traversal which keeps markdown files only, all dirs are recursed.
+  pub fn init_markdown(
+    dir: &str,
+    depth: Option<usize>,
+    read_ctn: impl Into<ReadMode>,
+  ) -> TreeResult<Self> {
+    let opts = WalkOptions {
+      markdown_only: true,
+      ..WalkOptions::default()
+    };
+    Self::init(dir, depth, read_ctn, &opts)
+  }
+
+  // Traverses each of `dirs` and grafts the trees side by side below a
+  // virtual root, so `children_vec` has a node per directory. Content isn't
+  // read. A directory given twice shows up once.
+  pub fn init_multi(dirs: &[&str], opts: &WalkOptions) -> TreeResult<Self> {
+    let mut inner = Arena::new();
+    let root = inner.new_node(Node::virtual_root());
+    let mut forest = Self::new(inner, root);
+    forest.opts = opts.clone();
+
+    for dir in dirs {
+      let tree = Self::init(dir, None, ReadMode::None, opts)?;
+      let tree_root = tree.inner[tree.root].get();
+      if forest.paths.contains_key(tree_root.path()) {
+        continue;
+      }
+      forest.graft(root, &tree, tree.root);
+    }
+
+    Ok(forest)
+  }
+
+  // Walks `dir` in the background and hands out the nodes as they are found,
+  // for rendering before the tree is complete. Children may come before
+  // their parent. The channel closes once the walk is done, dropping the
+  // receiver stops the walk. Content isn't read, unreadable entries are left
+  // out.
+  pub fn stream(dir: &str, opts: &WalkOptions) -> TreeResult<Receiver<Node>> {
+    let walker = new_walker(Path::new(dir), None, opts, None)?;
+    let budget = opts.max_nodes.map(|max| Arc::new(NodeBudget::new(max)));
+    let max_symlink_depth = opts.max_symlink_depth;
+    let (tx, rx) = channel::unbounded::<Node>();
+
+    thread::spawn(move || {
+      let (state_tx, state_rx) = channel::unbounded::<TraversalState>();
+
+      thread::scope(|s| {
+        s.spawn(|| {
+          let mut visitor_builder = BranchVisitorBuilder::new(
+            Sender::clone(&state_tx),
+            ContentReader::default(),
+          );
+          if let Some(budget) = budget {
+            visitor_builder = visitor_builder.with_budget(budget);
+          }
+          visitor_builder =
+            visitor_builder.with_max_symlink_depth(max_symlink_depth);
+          walker.visit(&mut visitor_builder);
+          state_tx.send(TraversalState::Done).unwrap_or(());
+        });
+
+        for state in &state_rx {
+          match state {
+            TraversalState::Ongoing(node) => {
+              if tx.send(node).is_err() {
+                break;
+              }
+            }
+            TraversalState::Failed(_) => continue,
+            TraversalState::Done => break,
+          }
+        }
+        // lets the walkers quit
+        drop(state_rx);
+      });
+    });
+
+    Ok(rx)
+  }
+
+  // How content is read for nodes added later, as during the traversal
+  pub(crate) fn content_reader(&self) -> ContentReader {
+    ContentReader::new(self.read_ctn, &self.opts)
+  }
+
+  // If the tree is partial as the walk hit `WalkOptions::max_nodes`.
+  pub fn truncated(&self) -> bool {
+    self.truncated
   }
 
   // Grabs a reference to `inner`.
@@ -52,15 +487,25 @@ impl Tree {
     &self.inner
   }
 
-  // Parallel traversal of the directory
+  // Parallel traversal of the directory, `glob_root` is what the globs of
+  // `opts` are relative to if not `dir`. Set for the walk of a subtree, whose
+  // nodes then keep the paths they have in the tree.
   fn traverse(
-    dir: &str,
+    dir: &Path,
     depth: Option<usize>,
-    read_ctn: bool,
-  ) -> TreeResult<(Arena<Node>, NodeId)> {
-    let walker = new_walker(PathBuf::from(dir), depth)?;
+    read_ctn: ReadMode,
+    opts: &WalkOptions,
+    glob_root: Option<&Path>,
+    hooks: &TraverseHooks,
+  ) -> TreeResult<Traversal> {
+    let depth = if opts.lazy_dirs {
+      Some(depth.map_or(1, |max| max.min(1)))
+    } else {
+      depth
+    };
+    let walker = new_walker(dir, depth, opts, glob_root)?;
     let (tx, rx) = channel::unbounded::<TraversalState>();
-    
+
     thread::scope(|s| {
       let mut tree = Arena::new();
 
@@ -68,79 +513,171 @@ impl Tree {
         // Key represents path of parent directory and values represent children.
         let mut branches: HashMap<PathBuf, Vec<NodeId>> = HashMap::new();
         let mut root_id = None;
-
-        while let Ok(TraversalState::Ongoing(node)) = rx.recv() {
-          if node.is_dir() {
-            let node_path = node.path();
-
-            if !branches.contains_key(node_path) {
-              branches.insert(node_path.to_owned(), vec![]);
-            }
-
-            if node.depth == 0 {
-              root_id = Some(tree.new_node(node));
+        let mut discovered = 0;
+        let mut errors = Vec::new();
+
+        loop {
+          let mut node = match rx.recv() {
+            Ok(TraversalState::Ongoing(node)) => node,
+            Ok(TraversalState::Failed(error)) => {
+              errors.push(error);
               continue;
             }
+            Ok(TraversalState::Done) | Err(_) => break,
+          };
+          discovered += 1;
+          if discovered % PROGRESS_INTERVAL == 0 {
+            hooks.report(discovered);
+          }
+          // listed at the depth limit, but not entered
+          if node.is_dir() && depth.is_some_and(|max| node.depth >= max) {
+            node.set_loaded(false);
+          }
+
+          if node.depth == 0 {
+            root_id = Some(tree.new_node(node));
+            continue;
           }
 
           let parent = node
             .parent_path()
-            .ok_or(String::from("ExpectedParent"))?
+            .ok_or_else(|| TreeError::ExpectedParent(node.path().to_owned()))?
             .to_owned();
           let node_id = tree.new_node(node);
+          // children may arrive before their parent
+          branches.entry(parent).or_default().push(node_id);
+        }
 
-          if let None = branches
-            .get_mut(&parent)
-            .map(|mut_ref| mut_ref.push(node_id))
-          {
-            branches.insert(parent, vec![]);
-          }
+        if discovered % PROGRESS_INTERVAL != 0 {
+          hooks.report(discovered);
         }
 
-        let root = root_id.ok_or(String::from("MissingRoot"))?;
+        let root = root_id.ok_or(TreeError::MissingRoot)?;
 
         Self::assemble_tree(&mut tree, root, &mut branches);
 
-        Ok::<(Arena<Node>, NodeId), String>((tree, root))
+        Ok::<Traversal, TreeError>(Traversal {
+          inner: tree,
+          root,
+          errors,
+          truncated: false,
+          cancelled: false,
+        })
       });
 
-      let mut visitor_builder =
-        BranchVisitorBuilder::new(Sender::clone(&tx), read_ctn);
+      let budget = opts.max_nodes.map(|max| Arc::new(NodeBudget::new(max)));
+      let mut visitor_builder = BranchVisitorBuilder::new(
+        Sender::clone(&tx),
+        ContentReader::new(read_ctn, opts),
+      );
+      if let Some(budget) = &budget {
+        visitor_builder = visitor_builder.with_budget(Arc::clone(budget));
+      }
+      if let Some(cancel) = &hooks.cancel {
+        visitor_builder = visitor_builder.with_cancel(Arc::clone(cancel));
+      }
+      if let Some(permits) = opts.content_concurrency {
+        visitor_builder =
+          visitor_builder.with_read_gate(Arc::new(ReadGate::new(permits)));
+      }
+      visitor_builder =
+        visitor_builder.with_max_symlink_depth(opts.max_symlink_depth);
+      // links in a cycle point within the tree too, the rest the walker
+      // tells apart itself
+      if opts.follow_links {
+        let tree_root = glob_root.unwrap_or(dir);
+        if let Ok(tree_root) = canonicalize(tree_root) {
+          visitor_builder = visitor_builder.with_tree_root(tree_root);
+        }
+      }
 
       walker.visit(&mut visitor_builder);
 
       tx.send(TraversalState::Done).unwrap_or(());
 
-      res.join().unwrap()
+      let mut traversal = res.join().map_err(|_| TreeError::TraversalPanicked)??;
+      traversal.truncated = budget.is_some_and(|budget| budget.exceeded());
+      traversal.cancelled = hooks
+        .cancel
+        .as_ref()
+        .is_some_and(|cancel| cancel.load(Ordering::Relaxed));
+      Ok(traversal)
     })
   }
 
-  // Takes the results of the parallel traversal and uses it to construct the tree
-  fn assemble_tree(
+  // Takes the results of the parallel traversal and uses it to construct the tree.
+  //
+  // Works off an explicit stack, deep hierarchies can't overflow. Every path
+  // of `branches` is taken once, so a cycle in there ends the descent.
+  pub(crate) fn assemble_tree(
     tree: &mut Arena<Node>,
-    current_node_id: NodeId,
+    root: NodeId,
     branches: &mut HashMap<PathBuf, Vec<NodeId>>,
   ) {
-    let current_node = tree[current_node_id].get_mut();
-
-    let children = branches.remove(current_node.path()).unwrap_or_default();
-    for child_id in children.iter() {
-      let index = *child_id;
-
-      let is_dir = {
-        let inner = tree[index].get();
-        inner.is_dir()
+    let mut stack = vec![root];
+
+    while let Some(current_node_id) = stack.pop() {
+      let children = match tree.get(current_node_id) {
+        Some(current_node) => branches
+          .remove(current_node.get().path())
+          .unwrap_or_default(),
+        None => continue,
       };
 
-      if is_dir {
-        Self::assemble_tree(tree, index, branches);
+      // Append children to current node.
+      for child_id in children {
+        if current_node_id.checked_append(child_id, tree).is_ok() {
+          stack.push(child_id);
+        }
       }
+      count_children(tree, current_node_id);
+    }
+  }
+
+  // Looks up the node of a path, `./foo` and `foo` are the same. A path as
+  // stored is taken as is, canonicalizing would resolve a followed link.
+  pub fn find_by_path(&self, path: &Path) -> Option<NodeId> {
+    if let Some(id) = self.paths.get(path) {
+      return Some(*id);
+    }
+    let key = canonical_path(path).unwrap_or_else(|_| path.to_owned());
+    self.paths.get(&key).copied()
+  }
+
+  // Path of the node relative to the root, empty for the root itself. In a
+  // forest of `init_multi` it starts with the name of the top directory.
+  pub fn relative_path(&self, node_id: NodeId) -> Option<PathBuf> {
+    let path = self.inner.get(node_id)?.get().path();
+    if node_id == self.root {
+      return Some(PathBuf::new());
     }
+    let top_id = self.top_dir(node_id)?;
+    let top = self.inner[top_id].get();
+    let base = if top_id == self.root {
+      top.path()
+    } else {
+      top.parent_path()?
+    };
+    path.strip_prefix(base).ok().map(Path::to_path_buf)
+  }
 
-    // Append children to current node.
-    for child_id in children {
-      current_node_id.append(child_id, tree);
+  // The directory a walk of `node_id` started from: the root, or in a
+  // forest the top directory above the node
+  fn top_dir(&self, node_id: NodeId) -> Option<NodeId> {
+    if !self.inner[self.root].get().is_virtual() {
+      return Some(self.root);
     }
+    node_id
+      .ancestors(&self.inner)
+      .find(|id| self.inner[*id].parent() == Some(self.root))
+  }
+
+  // Sums the size of all files below the node.
+  pub fn dir_size(&self, node_id: NodeId) -> u64 {
+    node_id
+      .descendants(&self.inner)
+      .map(|id| self.inner[id].get().size)
+      .sum()
   }
 
   pub fn children_vec(&self) -> Vec<Node> {
@@ -159,28 +696,128 @@ impl Tree {
   }
 }
 
+// Outcome of `Tree::traverse`
+struct Traversal {
+  inner: Arena<Node>,
+  root: NodeId,
+  errors: Vec<EntryError>,
+  truncated: bool,
+  cancelled: bool,
+}
+
+// Optional callbacks of a traversal
+#[derive(Default)]
+struct TraverseHooks<'a> {
+  progress: Option<&'a (dyn Fn(usize) + Sync)>,
+  cancel: Option<Arc<AtomicBool>>,
+}
+
+impl TraverseHooks<'_> {
+  fn report(&self, discovered: usize) {
+    if let Some(progress) = self.progress {
+      progress(discovered);
+    }
+  }
+}
+
+// Hash of a relative path, the same on all platforms
+fn path_uid(relative: &Path) -> u64 {
+  let components: Vec<Cow<'_, str>> = relative
+    .components()
+    .map(|c| c.as_os_str().to_string_lossy())
+    .collect();
+  hash_bytes(components.join("/").as_bytes())
+}
+
+// `fs::canonicalize` without the verbatim prefix of Windows, this is the
+// form of all paths in the tree
+pub(crate) fn canonicalize<P: AsRef<Path>>(path: P) -> io::Result<PathBuf> {
+  fs::canonicalize(path).map(strip_verbatim)
+}
+
+// Canonicalizes the parent only, so a symlink keeps its own path
+fn canonical_path(path: &Path) -> io::Result<PathBuf> {
+  match (path.parent(), path.file_name()) {
+    (Some(parent), Some(name)) if !parent.as_os_str().is_empty() => {
+      Ok(canonicalize(parent)?.join(name))
+    }
+    _ => canonicalize(path),
+  }
+}
+
+// walker threads beyond the cores help with slow disks, thousands don't
+const MAX_THREADS_PER_CORE: usize = 4;
+
 // Build a new Parallel walker
-fn new_walker(dir: PathBuf, depth: Option<usize>) -> Result<WalkParallel, String> {
-  let root = fs::canonicalize(dir).map_err(|e| (format!("{e}")))?;
-
-  fs::metadata(&root).map_err(|e| (format!("Not Found {}: {e}", root.display())))?;
-
-  Ok(
-    WalkBuilder::new(root)
-      .max_depth(depth)
-      .follow_links(false)
-      .git_ignore(false)
-      .hidden(true)
-      .threads(default_threads_num())
-      .build_parallel(),
-  )
+fn new_walker(
+  dir: &Path,
+  depth: Option<usize>,
+  opts: &WalkOptions,
+  glob_root: Option<&Path>,
+) -> TreeResult<WalkParallel> {
+  // a followed link to a directory of the tree isn't resolved to its target
+  let root = match glob_root {
+    Some(_) => canonical_path(dir),
+    None => canonicalize(dir),
+  }
+  .map_err(|e| TreeError::from_io(e, dir))?;
+
+  fs::metadata(&root)?;
+
+  let filter = EntryFilter::new(glob_root.unwrap_or(&root), opts)
+    .map_err(|e| TreeError::InvalidGlob(e.to_string()))?;
+
+  let mut builder = WalkBuilder::new(root);
+  builder
+    .max_depth(depth)
+    .follow_links(opts.follow_links)
+    .git_ignore(opts.git_ignore)
+    .ignore(opts.ignore)
+    .git_global(opts.git_global)
+    .require_git(!opts.git_ignore)
+    .hidden(!opts.show_hidden)
+    .threads(threads_num(opts.threads));
+
+  for file_name in &opts.custom_ignore_files {
+    builder.add_custom_ignore_filename(file_name);
+  }
+
+  if let Some(filter) = filter {
+    builder.filter_entry(move |entry| {
+      let is_dir = entry.file_type().map(|ft| ft.is_dir()).unwrap_or(false);
+      filter.keep(entry.path(), is_dir)
+    });
+  }
+
+  Ok(builder.build_parallel())
+}
+
+// Refreshes `direct_child_count` and `direct_file_count` of a node
+fn count_children(tree: &mut Arena<Node>, node_id: NodeId) {
+  let (mut children, mut files) = (0, 0);
+  for child_id in node_id.children(tree) {
+    children += 1;
+    if !tree[child_id].get().is_dir() {
+      files += 1;
+    }
+  }
+  let node = tree[node_id].get_mut();
+  node.direct_child_count = children;
+  node.direct_file_count = files;
 }
 
 // default amount of parallelism
 fn default_threads_num() -> usize {
-  available_parallelism()
-    .unwrap_or_else(|_| NonZeroUsize::new(1).unwrap())
-    .get()
+  available_parallelism().unwrap_or(NonZeroUsize::MIN).get()
+}
+
+// requested amount of parallelism, 0 meaning 1; no more than
+// [MAX_THREADS_PER_CORE] per core
+fn threads_num(threads: Option<usize>) -> usize {
+  match threads {
+    Some(n) => n.clamp(1, default_threads_num() * MAX_THREADS_PER_CORE),
+    None => default_threads_num(),
+  }
 }
 
 pub fn assemble_note_tree(
@@ -1,26 +1,88 @@
+use super::{
+  content::{self, Heading},
+  kind::{looks_binary, utf16_endian, FileKind, MAGIC_BYTES, SNIFF_BYTES},
+  options::{is_note, Encoding, ReadMode, ReadPredicate, WalkOptions},
+};
 use crate::{
-  files::{check_hidden, check_md, FileMetaData},
+  files::{check_hidden, FileMetaData},
   paths::PathExt,
 };
+use encoding_rs::{UTF_16BE, UTF_16LE, WINDOWS_1252};
 use ignore::DirEntry;
 use indextree::{Arena, Node as NodeWrapper, NodeId};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::{
   borrow::Cow,
   convert::{From, Into},
   ffi::{OsStr, OsString},
   fs::{self, FileType, Metadata},
+  io::{self, BufRead, BufReader, Read},
   path::{Path, PathBuf},
   time::SystemTime,
 };
+use xxhash_rust::xxh3::{xxh3_64, Xxh3};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Node {
   pub depth: usize,
+  #[serde(serialize_with = "ser_os_string", deserialize_with = "de_os_string")]
   file_name: OsString,
+  #[serde(skip)]
   file_type: Option<FileType>,
+  #[serde(skip)]
   pub file_meta: Option<Metadata>,
   pub file_text: Option<String>,
   path: PathBuf,
+  // kept apart from `file_type` so it survives serialization
+  is_dir: bool,
+  // also set for followed links, which are dirs or files otherwise
+  is_symlink: bool,
+  kind: FileKind,
+  // file size in bytes, 0 for directories
+  pub size: u64,
+  // summed size below a directory, see `Tree::compute_dir_sizes`
+  pub recursive_size: u64,
+  // entries right below a directory, and the files among them; kept up to
+  // date by the edits of the tree
+  pub direct_child_count: usize,
+  pub direct_file_count: usize,
+  // None if not supported by the platform or filesystem
+  modified: Option<SystemTime>,
+  created: Option<SystemTime>,
+  // parsed from the content, None if missing or malformed
+  pub frontmatter: Option<serde_json::Value>,
+  // words of the body, 0 if content isn't read
+  pub word_count: usize,
+  // `word_count` at `WalkOptions::words_per_minute`, rounded up; None if
+  // content isn't read or only a preview is
+  pub reading_time_secs: Option<u32>,
+  // inline `#tags` without the `#`
+  pub tags: Vec<String>,
+  // targets of outbound `[[wikilinks]]`
+  pub links: Vec<String>,
+  // targets of inline `[text](target)` links as written, see
+  // `WalkOptions::image_links`
+  pub md_links: Vec<String>,
+  // outline of the note, see `content::headings`
+  pub headings: Vec<Heading>,
+  // language of each fenced code block, empty if unlabeled; see
+  // `Tree::code_language_stats`
+  pub code_languages: Vec<String>,
+  // why the content couldn't be read, `file_text` is None then
+  pub read_error: Option<String>,
+  // xxh3 of the file bytes, only if read in full
+  pub content_hash: Option<u64>,
+  // over `WalkOptions::max_content_bytes`, `file_text` is None then
+  pub content_skipped: bool,
+  // sniffed from the first bytes, false if content isn't read
+  pub is_binary: bool,
+  // see `Node::virtual_root`
+  is_virtual: bool,
+  // false for directories whose children aren't walked yet, see
+  // `Tree::expand`
+  is_loaded: bool,
+  // see `Node::uid`
+  uid: u64,
 }
 
 impl Node {
@@ -33,14 +95,141 @@ impl Node {
     file_text: Option<String>,
     path: PathBuf,
   ) -> Self {
-    Self {
+    let size = file_meta
+      .as_ref()
+      .filter(|meta| !meta.is_dir())
+      .map(|meta| meta.len())
+      .unwrap_or(0);
+    let modified = file_meta.as_ref().and_then(|meta| meta.modified().ok());
+    let created = file_meta.as_ref().and_then(|meta| meta.created().ok());
+    let is_dir = file_type.map(|ft| ft.is_dir()).unwrap_or(false);
+    let kind = if is_dir {
+      FileKind::Directory
+    } else {
+      FileKind::from_path(&path)
+    };
+
+    let mut node = Self {
       depth,
       file_name,
       file_type,
       file_meta,
       file_text,
       path,
+      is_dir,
+      is_symlink: file_type.is_some_and(|ft| ft.is_symlink()),
+      kind,
+      size,
+      recursive_size: 0,
+      direct_child_count: 0,
+      direct_file_count: 0,
+      modified,
+      created,
+      frontmatter: None,
+      word_count: 0,
+      reading_time_secs: None,
+      tags: Vec::new(),
+      links: Vec::new(),
+      md_links: Vec::new(),
+      headings: Vec::new(),
+      code_languages: Vec::new(),
+      read_error: None,
+      content_hash: None,
+      content_skipped: false,
+      is_binary: false,
+      is_virtual: false,
+      is_loaded: true,
+      uid: 0,
+    };
+    node.index_content(&ContentReader::default());
+    node
+  }
+
+  // Display title: frontmatter `title`, else the first `# ` heading, else the
+  // file stem.
+  pub fn title(&self) -> String {
+    let from_frontmatter = self
+      .frontmatter
+      .as_ref()
+      .and_then(|fm| fm.get("title"))
+      .and_then(|title| title.as_str())
+      .map(|title| title.trim().to_string())
+      .filter(|title| !title.is_empty());
+
+    from_frontmatter
+      .or_else(|| self.file_text.as_deref().and_then(content::first_heading))
+      .unwrap_or_else(|| {
+        self
+          .path
+          .file_stem()
+          .map(|stem| stem.to_string_lossy().to_string())
+          .unwrap_or_else(|| self.file_name_lossy().to_string())
+      })
+  }
+
+  // Derives the content fields from `file_text`
+  fn index_content(&mut self, reader: &ContentReader) {
+    let text = match self.file_text.as_deref() {
+      Some(text) => text,
+      None => return,
+    };
+
+    self.frontmatter = content::parse_frontmatter(text);
+    self.word_count = content::word_count(text);
+    // a preview would understate it
+    if !matches!(reader.mode, ReadMode::Preview(_)) {
+      self.reading_time_secs = Some(reading_time(self.word_count, reader.wpm));
     }
+    self.tags = content::tags(text);
+    self.links = content::wikilinks(text);
+    self.md_links = content::md_links(text, reader.image_links);
+    self.headings = content::headings(text);
+    self.code_languages = content::code_languages(text);
+  }
+
+  // Builds a node straight from the filesystem, outside of a traversal.
+  // Links are followed as `WalkOptions::follow_links` says.
+  pub fn from_path(
+    path: &Path,
+    depth: usize,
+    reader: &ContentReader,
+  ) -> io::Result<Self> {
+    let metadata = if reader.follow_links {
+      fs::metadata(path)?
+    } else {
+      fs::symlink_metadata(path)?
+    };
+    let file_name = path.file_name().map_or_else(
+      || OsString::from(path.display().to_string()),
+      |os_str| os_str.to_owned(),
+    );
+    let read = reader.read(path, Some(&metadata));
+
+    let mut node = Self::new(
+      depth,
+      file_name,
+      Some(metadata.file_type()),
+      Some(metadata),
+      None,
+      path.to_owned(),
+    );
+    node.apply_read(read, reader);
+    node.sniff_kind(reader);
+    // the type of a followed link is the one of its target
+    if reader.follow_links {
+      node.is_symlink = fs::symlink_metadata(path)?.file_type().is_symlink();
+    }
+    Ok(node)
+  }
+
+  // Directory on top of a forest of trees, it has no name nor path and
+  // doesn't exist on disk.
+  pub(crate) fn virtual_root() -> Self {
+    let mut node = Self::new(0, OsString::new(), None, None, None, PathBuf::new());
+    node.is_dir = true;
+    node.kind = FileKind::Directory;
+    node.is_virtual = true;
+    node
   }
 
   pub fn file_name(&self) -> &OsStr {
@@ -56,7 +245,92 @@ impl Node {
   }
 
   pub fn is_dir(&self) -> bool {
-    self.file_type().map(|ft| ft.is_dir()).unwrap_or(false)
+    self.is_dir
+  }
+
+  // Reads the whole text from disk with the settings of `reader`, regardless
+  // of what was loaded. None for nodes whose content is never read, e.g.
+  // non-markdown files.
+  pub fn load_text(&self, reader: &ContentReader) -> io::Result<Option<String>> {
+    // not kept by a cache
+    let metadata = match &self.file_meta {
+      Some(meta) => Cow::Borrowed(meta),
+      None => Cow::Owned(fs::metadata(&self.path)?),
+    };
+    reader
+      .full()
+      .read(&self.path, Some(&metadata))
+      .map(|read| read.and_then(|content| content.text))
+  }
+
+  // Stores the outcome of reading the file
+  fn apply_read(
+    &mut self,
+    read: io::Result<Option<Content>>,
+    reader: &ContentReader,
+  ) {
+    match read {
+      Ok(Some(content)) => {
+        self.file_text = content.text;
+        self.content_hash = content.hash;
+        self.is_binary = content.is_binary;
+        self.content_skipped = content.skipped;
+        self.index_content(reader);
+      }
+      Ok(None) => {}
+      Err(e) => self.read_error = Some(e.to_string()),
+    }
+  }
+
+  // Tells the kind of files without a telling extension from their first
+  // bytes, see `WalkOptions::sniff_kind`
+  fn sniff_kind(&mut self, reader: &ContentReader) {
+    let is_file = self.file_meta.as_ref().is_some_and(|meta| meta.is_file());
+    if !reader.sniff_kind || !is_file || !self.kind.is_ambiguous() {
+      return;
+    }
+
+    if let Ok(head) = read_head(&self.path, MAGIC_BYTES) {
+      let has_extension = self.path.extension().is_some();
+      if let Some(kind) = FileKind::from_head(&head, has_extension) {
+        self.kind = kind;
+      }
+    }
+  }
+
+  pub fn is_virtual(&self) -> bool {
+    self.is_virtual
+  }
+
+  // Id kept across traversals, a hash of the path relative to the root.
+  // Changes with a rename, 0 until the node is part of a [Tree].
+  pub fn uid(&self) -> u64 {
+    self.uid
+  }
+
+  pub(crate) fn set_uid(&mut self, uid: u64) {
+    self.uid = uid;
+  }
+
+  pub fn is_loaded(&self) -> bool {
+    self.is_loaded
+  }
+
+  pub(crate) fn set_loaded(&mut self, is_loaded: bool) {
+    self.is_loaded = is_loaded;
+  }
+
+  pub fn is_symlink(&self) -> bool {
+    self.is_symlink
+  }
+
+  pub fn kind(&self) -> FileKind {
+    self.kind
+  }
+
+  // Emoji hint of the kind, see `FileKind::icon`
+  pub fn icon(&self) -> &'static str {
+    self.kind.icon()
   }
 
   pub fn file_type(&self) -> Option<&FileType> {
@@ -67,13 +341,93 @@ impl Node {
     self.path.parent()
   }
 
+  // Points the node at its new location after a rename or move, the kind
+  // of a file follows its extension
+  pub(crate) fn set_path(&mut self, path: PathBuf) {
+    if let Some(file_name) = path.file_name() {
+      self.file_name = file_name.to_owned();
+    }
+    if !self.is_dir {
+      self.kind = FileKind::from_path(&path);
+    }
+    self.path = path;
+  }
+
   pub fn path(&self) -> &Path {
     &self.path
   }
+
+  pub fn modified(&self) -> Option<SystemTime> {
+    self.modified
+  }
+
+  pub fn created(&self) -> Option<SystemTime> {
+    self.created
+  }
+
+  // How long before `now` the node was modified, e.g. `5 minutes ago`,
+  // rounded down to the largest unit. None without a modification time.
+  pub fn modified_ago(&self, now: SystemTime) -> Option<String> {
+    // a timestamp ahead of `now` is taken for clock skew
+    let elapsed = now.duration_since(self.modified?).unwrap_or_default();
+    Some(time_ago(elapsed.as_secs()))
+  }
+}
+
+// Units of `time_ago` from the largest, in seconds
+const AGO_UNITS: [(&str, u64); 6] = [
+  ("year", 365 * 24 * 60 * 60),
+  ("month", 30 * 24 * 60 * 60),
+  ("week", 7 * 24 * 60 * 60),
+  ("day", 24 * 60 * 60),
+  ("hour", 60 * 60),
+  ("minute", 60),
+];
+
+// `secs` in the largest unit fitting, `just now` for less than a minute
+fn time_ago(secs: u64) -> String {
+  AGO_UNITS
+    .iter()
+    .find(|(_, unit)| secs >= *unit)
+    .map_or_else(
+      || "just now".to_string(),
+      |(name, unit)| match secs / unit {
+        1 => format!("1 {name} ago"),
+        n => format!("{n} {name}s ago"),
+      },
+    )
+}
+
+// Seconds to read `words` at `wpm`, rounded up
+fn reading_time(words: usize, wpm: u32) -> u32 {
+  let secs = (words as u64 * 60).div_ceil(wpm as u64);
+  u32::try_from(secs).unwrap_or(u32::MAX)
 }
 
-impl From<(&DirEntry, bool)> for Node {
-  fn from((dir_entry, ctn): (&DirEntry, bool)) -> Self {
+fn ser_os_string<S: Serializer>(
+  name: &OsString,
+  serializer: S,
+) -> Result<S::Ok, S::Error> {
+  serializer.serialize_str(&name.to_string_lossy())
+}
+
+fn de_os_string<'de, D: Deserializer<'de>>(
+  deserializer: D,
+) -> Result<OsString, D::Error> {
+  String::deserialize(deserializer).map(OsString::from)
+}
+
+/// Nested form of the tree, free of arena internals.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SerializableNode {
+  pub name: String,
+  pub path: PathBuf,
+  pub is_dir: bool,
+  pub children: Vec<SerializableNode>,
+}
+
+impl From<(&DirEntry, &ContentReader)> for Node {
+  fn from((dir_entry, reader): (&DirEntry, &ContentReader)) -> Self {
     let depth = dir_entry.depth();
     let file_type = dir_entry.file_type();
     let path = dir_entry.path();
@@ -84,25 +438,288 @@ impl From<(&DirEntry, bool)> for Node {
     );
 
     let metadata = dir_entry.metadata().ok();
-    let text = if ctn {
-      match metadata.clone() {
-        Some(meta) => {
-          if meta.is_file() && check_md(&path.display().to_string()) {
-            match fs::read_to_string(path) {
-              Ok(text) => Some(text),
-              Err(_e) => None,
-            }
-          } else {
-            None
-          }
+    let read = reader.read(path, metadata.as_ref());
+
+    let mut node =
+      Self::new(depth, file_name, file_type, metadata, None, path.into());
+    node.apply_read(read, reader);
+    node.sniff_kind(reader);
+    // the type of a followed link is the one of its target
+    node.is_symlink = dir_entry.path_is_symlink();
+    node
+  }
+}
+
+// What was read of a file
+struct Content {
+  // markdown files only
+  text: Option<String>,
+  // of all bytes, None for a preview
+  hash: Option<u64>,
+  is_binary: bool,
+  // too large to be read, see `WalkOptions::max_content_bytes`
+  skipped: bool,
+}
+
+/// How file content is read, the [ReadMode] plus the decoding settings of
+/// [WalkOptions].
+#[derive(Debug, Clone)]
+pub struct ContentReader {
+  mode: ReadMode,
+  encoding: Encoding,
+  image_links: bool,
+  wpm: u32,
+  sniff_kind: bool,
+  read_if: Option<ReadPredicate>,
+  max_bytes: Option<u64>,
+  follow_links: bool,
+}
+
+impl Default for ContentReader {
+  fn default() -> Self {
+    Self::new(ReadMode::None, &WalkOptions::default())
+  }
+}
+
+impl ContentReader {
+  pub fn new(mode: ReadMode, opts: &WalkOptions) -> Self {
+    Self {
+      mode,
+      encoding: opts.encoding,
+      image_links: opts.image_links,
+      wpm: opts.words_per_minute.max(1),
+      sniff_kind: opts.sniff_kind,
+      read_if: opts.read_if.clone(),
+      max_bytes: opts.max_content_bytes,
+      follow_links: opts.follow_links,
+    }
+  }
+
+  pub fn mode(&self) -> ReadMode {
+    self.mode
+  }
+
+  // Same settings, reading any file in full
+  fn full(&self) -> Self {
+    Self {
+      mode: ReadMode::Full,
+      read_if: None,
+      ..self.clone()
+    }
+  }
+
+  // Reads the text of markdown files as the mode says. Other files are only
+  // sniffed, files turned down by `WalkOptions::read_if` not at all. Markdown
+  // files over `WalkOptions::max_content_bytes` are told by their size alone.
+  fn read(
+    &self,
+    path: &Path,
+    metadata: Option<&Metadata>,
+  ) -> io::Result<Option<Content>> {
+    if self.mode.is_none() || !metadata.is_some_and(|meta| meta.is_file()) {
+      return Ok(None);
+    }
+    if self
+      .read_if
+      .as_ref()
+      .is_some_and(|read_if| !read_if.accepts(path))
+    {
+      return Ok(None);
+    }
+
+    if !is_note(path) {
+      return Ok(Some(Content {
+        text: None,
+        hash: None,
+        is_binary: looks_binary(&read_head(path, SNIFF_BYTES)?),
+        skipped: false,
+      }));
+    }
+    let len = metadata.map_or(0, |meta| meta.len());
+    if self.max_bytes.is_some_and(|max_bytes| len > max_bytes) {
+      return Ok(Some(Content {
+        text: None,
+        hash: None,
+        is_binary: false,
+        skipped: true,
+      }));
+    }
+
+    let content = match self.mode {
+      ReadMode::Preview(chars) if self.encoding == Encoding::Utf8 => Content {
+        text: Some(read_preview(fs::File::open(path)?, chars)?),
+        hash: None,
+        is_binary: looks_binary(&read_head(path, SNIFF_BYTES)?),
+        skipped: false,
+      },
+      // frontmatter can only be told apart once decoded
+      ReadMode::Preview(chars) => {
+        let bytes = read_preview_bytes(path, chars)?;
+        let is_binary = looks_binary(&bytes[..bytes.len().min(SNIFF_BYTES)]);
+        let text = decode(bytes, self.encoding);
+        Content {
+          text: Some(preview_of(&text, chars)),
+          hash: None,
+          is_binary,
+          skipped: false,
+        }
+      }
+      _ => {
+        let bytes = fs::read(path)?;
+        Content {
+          hash: Some(hash_bytes(&bytes)),
+          is_binary: looks_binary(&bytes[..bytes.len().min(SNIFF_BYTES)]),
+          text: Some(decode(bytes, self.encoding)),
+          skipped: false,
         }
-        _ => None,
       }
-    } else {
-      None
     };
+    Ok(Some(content))
+  }
+}
+
+// Just the mode, UTF-8 otherwise
+impl From<ReadMode> for ContentReader {
+  fn from(mode: ReadMode) -> Self {
+    Self {
+      mode,
+      ..Self::default()
+    }
+  }
+}
+
+// The first `len` bytes of a file
+fn read_head(path: &Path, len: usize) -> io::Result<Vec<u8>> {
+  let mut head = Vec::with_capacity(len);
+  fs::File::open(path)?
+    .take(len as u64)
+    .read_to_end(&mut head)?;
+  Ok(head)
+}
+
+// most bytes a preview spends on a frontmatter block
+pub(crate) const FRONTMATTER_CAP: u64 = 64 * 1024;
+
+// Reads no further than needed for `chars` chars of the body. A frontmatter
+// block not closed within [FRONTMATTER_CAP] bytes is taken for no
+// frontmatter, the preview starts at the top then.
+pub(crate) fn read_preview<R: Read>(source: R, chars: usize) -> io::Result<String> {
+  let mut reader = BufReader::new(source);
+  // a char takes 4 bytes at most
+  let max_bytes = chars.saturating_mul(4) as u64;
+  let limit = max_bytes.saturating_add(FRONTMATTER_CAP);
+
+  // a frontmatter block is only known as such once closed
+  let mut head = Vec::new();
+  reader
+    .by_ref()
+    .take(max_bytes)
+    .read_until(b'\n', &mut head)?;
+  let opening = head.strip_prefix("\u{feff}".as_bytes()).unwrap_or(&head);
+  let closing: &[&str] = if is_fence(opening, &["---"]) {
+    &["---", "..."]
+  } else if is_fence(opening, &["+++"]) {
+    &["+++"]
+  } else if opening.starts_with(b"{") {
+    // where a JSON block ends takes parsing it
+    let mut bytes = head;
+    let left = limit.saturating_sub(bytes.len() as u64);
+    reader.take(left).read_to_end(&mut bytes)?;
+    return Ok(preview_of(&decode_lossy(bytes), chars));
+  } else {
+    &[]
+  };
+  if !closing.is_empty() {
+    loop {
+      let start = head.len();
+      let left = limit.saturating_sub(start as u64);
+      // not closed, previewed from the start
+      if left == 0 || reader.by_ref().take(left).read_until(b'\n', &mut head)? == 0 {
+        break;
+      }
+      if is_fence(&head[start..], closing) {
+        head.clear();
+        break;
+      }
+    }
+  }
+
+  let mut bytes = head;
+  let left = max_bytes.saturating_sub(bytes.len() as u64);
+  reader.take(left).read_to_end(&mut bytes)?;
+  Ok(truncate_chars(&decode_lossy(bytes), chars))
+}
 
-    Self::new(depth, file_name, file_type, metadata, text, path.into())
+// As much of a file as a preview of `chars` chars may take, frontmatter
+// included
+fn read_preview_bytes(path: &Path, chars: usize) -> io::Result<Vec<u8>> {
+  let limit = (chars.saturating_mul(4) as u64).saturating_add(FRONTMATTER_CAP);
+  let mut bytes = Vec::new();
+  fs::File::open(path)?.take(limit).read_to_end(&mut bytes)?;
+  Ok(bytes)
+}
+
+// The first `chars` chars of the body
+fn preview_of(text: &str, chars: usize) -> String {
+  let body = content::split_frontmatter(text).map_or(text, |(_, body)| body);
+  truncate_chars(body, chars)
+}
+
+fn truncate_chars(text: &str, chars: usize) -> String {
+  match text.char_indices().nth(chars) {
+    Some((idx, _)) => text[..idx].to_string(),
+    None => text.to_string(),
+  }
+}
+
+fn is_fence(line: &[u8], fences: &[&str]) -> bool {
+  let line = String::from_utf8_lossy(line);
+  fences.contains(&line.trim_end())
+}
+
+// Decodes as `encoding` says, never fails
+fn decode(bytes: Vec<u8>, encoding: Encoding) -> String {
+  let decoder = match encoding {
+    Encoding::Utf8 => return decode_lossy(bytes),
+    Encoding::Other(decoder) => decoder,
+    Encoding::Auto => match encoding_rs::Encoding::for_bom(&bytes) {
+      Some((by_bom, _)) => by_bom,
+      None if std::str::from_utf8(&bytes).is_ok() => return decode_lossy(bytes),
+      None => match utf16_endian(&bytes[..bytes.len().min(SNIFF_BYTES)]) {
+        Some(true) => UTF_16LE,
+        Some(false) => UTF_16BE,
+        None => WINDOWS_1252,
+      },
+    },
+  };
+  // strips a BOM of its own
+  decoder.decode(&bytes).0.into_owned()
+}
+
+fn decode_lossy(bytes: Vec<u8>) -> String {
+  match String::from_utf8(bytes) {
+    Ok(text) => text,
+    Err(e) => String::from_utf8_lossy(e.as_bytes()).into_owned(),
+  }
+}
+
+// Stable across runs and platforms
+pub(crate) fn hash_bytes(bytes: &[u8]) -> u64 {
+  xxh3_64(bytes)
+}
+
+// Same as `hash_bytes` of the whole file, read in chunks
+pub(crate) fn hash_file(path: &Path) -> io::Result<u64> {
+  let mut file = fs::File::open(path)?;
+  let mut hasher = Xxh3::new();
+  let mut chunk = vec![0; 64 * 1024];
+  loop {
+    match file.read(&mut chunk) {
+      Ok(0) => return Ok(hasher.digest()),
+      Ok(len) => hasher.update(&chunk[..len]),
+      Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+      Err(e) => return Err(e),
+    }
   }
 }
 
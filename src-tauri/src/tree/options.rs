@@ -0,0 +1,262 @@
+use crate::files::check_md;
+use ignore::overrides::{Override, OverrideBuilder};
+use serde::{Deserialize, Serialize};
+use std::{fmt, path::Path, sync::Arc, time::Duration};
+
+/// Extensions recognized by the markdown-only mode.
+pub const MARKDOWN_EXTENSIONS: [&str; 5] = ["md", "markdown", "mdown", "mkd", "mdx"];
+
+/// Knobs of the directory walker, `Default` matches the original behavior.
+///
+/// Serializable to persist the settings of a user, missing fields take
+/// their default.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WalkOptions {
+  // follow symbolic links, directories already seen are skipped
+  pub follow_links: bool,
+  // links crossed along a path before linked directories aren't entered,
+  // apart from `depth`; usize::MAX for no limit
+  pub max_symlink_depth: usize,
+  // respect `.gitignore`, also outside of a git repo
+  pub git_ignore: bool,
+  // respect `.ignore` files
+  pub ignore: bool,
+  // extra gitignore-style files like `.mdignore`, always respected; these
+  // win over `.ignore` which wins over `.gitignore`
+  pub custom_ignore_files: Vec<String>,
+  // respect the global gitignore of git config
+  pub git_global: bool,
+  // include hidden entries, the root is always included
+  pub show_hidden: bool,
+  // keep only files matching any of these globs, e.g. `*.md`
+  pub include_globs: Vec<String>,
+  // drop files matching any of these globs
+  pub exclude_globs: Vec<String>,
+  // keep only markdown files, see [MARKDOWN_EXTENSIONS]
+  pub markdown_only: bool,
+  // walker threads, None for one per core; 0 means 1, more than 4 per core
+  // mean 4 per core
+  pub threads: Option<usize>,
+  // read content only for the files accepted, on top of the read mode and
+  // `markdown_only`; not serialized
+  #[serde(skip)]
+  pub read_if: Option<ReadPredicate>,
+  // markdown files larger than this many bytes keep no content, see
+  // `Node::content_skipped`; None for no limit
+  pub max_content_bytes: Option<u64>,
+  // how markdown files are decoded when content is read
+  pub encoding: Encoding,
+  // also take the targets of `![alt](image)` into `Node::md_links`
+  pub image_links: bool,
+  // reading speed behind `Node::reading_time_secs`; 0 means 1
+  pub words_per_minute: u32,
+  // files read at the same time across all walker threads, None for no
+  // limit; 0 means 1
+  pub content_concurrency: Option<usize>,
+  // stop the walk after this many nodes, the root included;
+  // see `Tree::truncated`
+  pub max_nodes: Option<usize>,
+  // drop directories ending up without files, see `Tree::prune_empty`
+  pub prune_empty_dirs: bool,
+  // list the directories of the root without entering them, see
+  // `Tree::expand`
+  pub lazy_dirs: bool,
+  // tell the kind of files without a known extension, or with a text one,
+  // from their first bytes; see `FileKind::from_head`
+  pub sniff_kind: bool,
+  // quiet period of `Tree::watch` before a burst of changes is released
+  pub watch_debounce: Duration,
+}
+
+impl Default for WalkOptions {
+  fn default() -> Self {
+    Self {
+      follow_links: false,
+      max_symlink_depth: usize::MAX,
+      git_ignore: false,
+      ignore: true,
+      custom_ignore_files: Vec::new(),
+      git_global: true,
+      show_hidden: false,
+      include_globs: Vec::new(),
+      exclude_globs: Vec::new(),
+      markdown_only: false,
+      threads: None,
+      read_if: None,
+      max_content_bytes: None,
+      encoding: Encoding::default(),
+      image_links: false,
+      words_per_minute: 200,
+      content_concurrency: None,
+      max_nodes: None,
+      prune_empty_dirs: false,
+      lazy_dirs: false,
+      sniff_kind: false,
+      watch_debounce: Duration::from_millis(200),
+    }
+  }
+}
+
+/// How much of the markdown files is read into `Node::file_text`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReadMode {
+  #[default]
+  None,
+  // the first N chars of the body, frontmatter stripped
+  Preview(usize),
+  Full,
+}
+
+impl ReadMode {
+  pub fn is_none(&self) -> bool {
+    *self == ReadMode::None
+  }
+}
+
+/// Decides per file from its path if the content is read, see
+/// `WalkOptions::read_if`. Predicates are equal only to their clones.
+#[derive(Clone)]
+pub struct ReadPredicate(Arc<dyn Fn(&Path) -> bool + Send + Sync>);
+
+impl ReadPredicate {
+  pub fn new<F>(predicate: F) -> Self
+  where
+    F: Fn(&Path) -> bool + Send + Sync + 'static,
+  {
+    Self(Arc::new(predicate))
+  }
+
+  pub fn accepts(&self, path: &Path) -> bool {
+    (self.0)(path)
+  }
+}
+
+impl fmt::Debug for ReadPredicate {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("ReadPredicate")
+  }
+}
+
+impl PartialEq for ReadPredicate {
+  fn eq(&self, other: &Self) -> bool {
+    Arc::ptr_eq(&self.0, &other.0)
+  }
+}
+
+/// How the bytes of markdown files are decoded into `Node::file_text`,
+/// invalid sequences end up as U+FFFD.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Encoding {
+  #[default]
+  Utf8,
+  // by BOM, else UTF-8 if valid, else UTF-16 by its NUL bytes, else
+  // Windows-1252
+  Auto,
+  // a BOM still takes precedence; serialized by its name, e.g. `Shift_JIS`
+  Other(#[serde(with = "encoding_label")] &'static encoding_rs::Encoding),
+}
+
+// `Encoding::Other` by the name of the encoding, any WHATWG label is
+// understood back
+mod encoding_label {
+  use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+  pub fn serialize<S: Serializer>(
+    encoding: &&'static encoding_rs::Encoding,
+    serializer: S,
+  ) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(encoding.name())
+  }
+
+  pub fn deserialize<'de, D: Deserializer<'de>>(
+    deserializer: D,
+  ) -> Result<&'static encoding_rs::Encoding, D::Error> {
+    let label = String::deserialize(deserializer)?;
+    encoding_rs::Encoding::for_label(label.as_bytes())
+      .ok_or_else(|| D::Error::custom(format!("unknown encoding: {label}")))
+  }
+}
+
+// the former `read_ctn: bool`
+impl From<bool> for ReadMode {
+  fn from(read_ctn: bool) -> Self {
+    if read_ctn {
+      ReadMode::Full
+    } else {
+      ReadMode::None
+    }
+  }
+}
+
+/// File filter built from the globs of [WalkOptions].
+///
+/// Directories are always kept so the folder structure is preserved.
+#[derive(Debug, Clone)]
+pub struct EntryFilter {
+  overrides: Option<Override>,
+  markdown_only: bool,
+}
+
+impl EntryFilter {
+  // None if there is nothing to filter
+  pub fn new(
+    root: &Path,
+    opts: &WalkOptions,
+  ) -> Result<Option<Self>, ignore::Error> {
+    let no_globs = opts.include_globs.is_empty() && opts.exclude_globs.is_empty();
+    if no_globs && !opts.markdown_only {
+      return Ok(None);
+    }
+    if no_globs {
+      return Ok(Some(Self {
+        overrides: None,
+        markdown_only: true,
+      }));
+    }
+
+    let mut builder = OverrideBuilder::new(root);
+    // mirror the case sensitivity of the filesystem
+    builder
+      .case_insensitive(cfg!(any(target_os = "macos", target_os = "windows")))?;
+    for glob in opts.include_globs.iter() {
+      builder.add(glob)?;
+    }
+    for glob in opts.exclude_globs.iter() {
+      builder.add(&format!("!{glob}"))?;
+    }
+
+    Ok(Some(Self {
+      overrides: Some(builder.build()?),
+      markdown_only: opts.markdown_only,
+    }))
+  }
+
+  pub fn keep(&self, path: &Path, is_dir: bool) -> bool {
+    if is_dir {
+      return true;
+    }
+    if self.markdown_only && !is_markdown(path) {
+      return false;
+    }
+    self
+      .overrides
+      .as_ref()
+      .map_or(true, |o| !o.matched(path, false).is_ignore())
+  }
+}
+
+// Check if the extension is one of [MARKDOWN_EXTENSIONS]
+pub fn is_markdown(path: &Path) -> bool {
+  path
+    .extension()
+    .and_then(|ext| ext.to_str())
+    .map(|ext| MARKDOWN_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+    .unwrap_or(false)
+}
+
+// Files whose text is read and linked, markdown and the plain text the app
+// opens as notes
+pub fn is_note(path: &Path) -> bool {
+  is_markdown(path) || check_md(&path.display().to_string())
+}
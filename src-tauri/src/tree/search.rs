@@ -0,0 +1,211 @@
+use super::{node::ContentReader, options::ReadMode, Tree, TreeError, TreeResult};
+use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
+use indextree::NodeId;
+use regex::Regex;
+use std::{borrow::Cow, cmp::Reverse};
+
+// Chars kept on each side of a match in [SearchHit::snippet]
+const SNIPPET_RADIUS: usize = 40;
+
+/// Line of a note matching a content search.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchHit {
+  pub node: NodeId,
+  // 1-based, frontmatter lines included
+  pub line: usize,
+  // the trimmed line, cut around the first match if long
+  pub snippet: String,
+}
+
+impl Tree {
+  // Nodes whose full file name, extension included, contains `query`.
+  // Depth-first, the root is a candidate too.
+  pub fn find_by_name(&self, query: &str, case_insensitive: bool) -> Vec<NodeId> {
+    let query = if case_insensitive {
+      query.to_lowercase()
+    } else {
+      query.to_string()
+    };
+
+    self
+      .root
+      .descendants(&self.inner)
+      .filter(|id| {
+        let name = self.inner[*id].get().file_name_lossy();
+        if case_insensitive {
+          name.to_lowercase().contains(&query)
+        } else {
+          name.contains(&query)
+        }
+      })
+      .collect()
+  }
+
+  // Nodes whose file name matches `pattern` anywhere, anchor it for a full
+  // match. Depth-first, the root is a candidate too.
+  pub fn find_by_regex(&self, pattern: &str) -> TreeResult<Vec<NodeId>> {
+    let regex = Regex::new(pattern).map_err(TreeError::InvalidRegex)?;
+
+    Ok(
+      self
+        .root
+        .descendants(&self.inner)
+        .filter(|id| regex.is_match(&self.inner[*id].get().file_name_lossy()))
+        .collect(),
+    )
+  }
+
+  // Best `limit` subsequence matches of `query` against the `/` separated
+  // path relative to the root, so folders help the ranking. Highest score
+  // first, ties go to the shorter path.
+  pub fn fuzzy_find(&self, query: &str, limit: usize) -> Vec<(NodeId, i64)> {
+    let matcher = SkimMatcherV2::default();
+    let root_path = self.inner[self.root].get().path();
+
+    let mut hits: Vec<(NodeId, i64, String)> = self
+      .root
+      .descendants(&self.inner)
+      .skip(1)
+      .filter_map(|id| {
+        let path = self.inner[id].get().path();
+        let relative = path
+          .strip_prefix(root_path)
+          .unwrap_or(path)
+          .components()
+          .map(|c| c.as_os_str().to_string_lossy())
+          .collect::<Vec<_>>()
+          .join("/");
+        let score = matcher.fuzzy_match(&relative, query)?;
+        Some((id, score, relative))
+      })
+      .collect();
+
+    hits.sort_by(|(_, a_score, a_path), (_, b_score, b_path)| {
+      (Reverse(a_score), a_path.len(), a_path).cmp(&(
+        Reverse(b_score),
+        b_path.len(),
+        b_path,
+      ))
+    });
+    hits
+      .into_iter()
+      .take(limit)
+      .map(|(id, score, _)| (id, score))
+      .collect()
+  }
+
+  // Lines of notes containing `query`, one hit per line, depth-first.
+  //
+  // Content which wasn't loaded fully by the traversal is read lazily from
+  // disk, unreadable files are skipped.
+  pub fn search_content(
+    &self,
+    query: &str,
+    case_insensitive: bool,
+  ) -> Vec<SearchHit> {
+    if query.is_empty() {
+      return Vec::new();
+    }
+    let query = if case_insensitive {
+      query.to_lowercase()
+    } else {
+      query.to_string()
+    };
+
+    let reader = self.content_reader();
+    self
+      .root
+      .descendants(&self.inner)
+      .flat_map(|id| self.node_hits(id, &query, case_insensitive, &reader))
+      .collect()
+  }
+
+  // Same as `search_content` with the notes scanned on the thread pool of
+  // rayon. Hits are ordered by path, then line.
+  #[cfg(feature = "parallel")]
+  pub fn search_content_par(
+    &self,
+    query: &str,
+    case_insensitive: bool,
+  ) -> Vec<SearchHit> {
+    use rayon::prelude::*;
+
+    if query.is_empty() {
+      return Vec::new();
+    }
+    let query = if case_insensitive {
+      query.to_lowercase()
+    } else {
+      query.to_string()
+    };
+
+    let reader = self.content_reader();
+    let ids: Vec<NodeId> = self.root.descendants(&self.inner).collect();
+    let mut hits: Vec<SearchHit> = ids
+      .into_par_iter()
+      .flat_map_iter(|id| self.node_hits(id, &query, case_insensitive, &reader))
+      .collect();
+    hits.sort_by(|a, b| {
+      let path = |hit: &SearchHit| self.inner[hit.node].get().path();
+      path(a).cmp(path(b)).then(a.line.cmp(&b.line))
+    });
+    hits
+  }
+
+  // Hits of `query` in the note of `id`, lowercased if `case_insensitive`
+  fn node_hits(
+    &self,
+    id: NodeId,
+    query: &str,
+    case_insensitive: bool,
+    reader: &ContentReader,
+  ) -> Vec<SearchHit> {
+    let node = self.inner[id].get();
+    if node.is_dir() {
+      return Vec::new();
+    }
+    let text = match (&node.file_text, self.read_ctn) {
+      (Some(text), ReadMode::Full) => Cow::Borrowed(text.as_str()),
+      _ => match node.load_text(reader) {
+        Ok(Some(text)) => Cow::Owned(text),
+        _ => return Vec::new(),
+      },
+    };
+
+    let mut hits = Vec::new();
+    for (idx, line) in text.lines().enumerate() {
+      let found = if case_insensitive {
+        let lowered = line.to_lowercase();
+        lowered.find(query).map(|at| lowered[..at].chars().count())
+      } else {
+        line.find(query).map(|at| line[..at].chars().count())
+      };
+      if let Some(at) = found {
+        hits.push(SearchHit {
+          node: id,
+          line: idx + 1,
+          snippet: snippet(line, at, query.chars().count()),
+        });
+      }
+    }
+    hits
+  }
+}
+
+// `line` around the match at char `at`, with `…` where cut
+fn snippet(line: &str, at: usize, len: usize) -> String {
+  let chars: Vec<char> = line.chars().collect();
+  let start = at.saturating_sub(SNIPPET_RADIUS);
+  let end = (at + len + SNIPPET_RADIUS).min(chars.len());
+  let start = start.min(end);
+
+  let mut snippet = String::new();
+  if start > 0 {
+    snippet.push('…');
+  }
+  snippet.extend(&chars[start..end]);
+  if end < chars.len() {
+    snippet.push('…');
+  }
+  snippet.trim().to_string()
+}
@@ -0,0 +1,162 @@
+use super::{node::Node, Tree, TreeError, TreeResult};
+use indextree::NodeId;
+use std::{cmp::Ordering, iter::Peekable, str::Chars};
+
+/// Key for [Tree::sort_children].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+  Name,
+  Modified,
+  Created,
+  Size,
+}
+
+/// Settings of [Tree::sort_children_with].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortOptions {
+  pub ascending: bool,
+  // directories precede files, each group still in key order
+  pub dirs_first: bool,
+  // runs of digits in names compare by their number, `Chapter 2` before
+  // `Chapter 10`
+  pub natural: bool,
+}
+
+impl Default for SortOptions {
+  fn default() -> Self {
+    Self {
+      ascending: true,
+      dirs_first: false,
+      natural: false,
+    }
+  }
+}
+
+impl Tree {
+  // Reorders the children of every directory, names compare case-insensitively.
+  // Errors without touching the order if a key needs metadata which isn't
+  // collected.
+  pub fn sort_children(&mut self, key: SortKey, ascending: bool) -> TreeResult<()> {
+    let opts = SortOptions {
+      ascending,
+      ..SortOptions::default()
+    };
+    self.sort_children_with(key, &opts)
+  }
+
+  // Same as `sort_children` with the settings of `opts`
+  pub fn sort_children_with(
+    &mut self,
+    key: SortKey,
+    opts: &SortOptions,
+  ) -> TreeResult<()> {
+    for node_id in self.root.descendants(&self.inner).skip(1) {
+      let node = self.inner[node_id].get();
+      let missing = match key {
+        SortKey::Name => false,
+        SortKey::Modified => node.modified().is_none(),
+        SortKey::Created => node.created().is_none(),
+        // `Node::size` outlives the metadata, e.g. in a cache
+        SortKey::Size => false,
+      };
+      if missing {
+        return Err(TreeError::MissingMetadata(node.path().to_owned()));
+      }
+    }
+
+    let dirs: Vec<NodeId> = self
+      .root
+      .descendants(&self.inner)
+      .filter(|id| self.inner[*id].get().is_dir())
+      .collect();
+
+    for dir in dirs {
+      let mut children: Vec<NodeId> = dir.children(&self.inner).collect();
+      children.sort_by(|a, b| {
+        let (a, b) = (self.inner[*a].get(), self.inner[*b].get());
+        let ord = if opts.ascending {
+          compare(a, b, key, opts.natural)
+        } else {
+          compare(a, b, key, opts.natural).reverse()
+        };
+        if opts.dirs_first {
+          b.is_dir().cmp(&a.is_dir()).then(ord)
+        } else {
+          ord
+        }
+      });
+
+      for child in children.iter() {
+        child.detach(&mut self.inner);
+      }
+      for child in children {
+        dir.append(child, &mut self.inner);
+      }
+    }
+
+    Ok(())
+  }
+}
+
+// Ties on metadata fall back to the name
+fn compare(a: &Node, b: &Node, key: SortKey, natural: bool) -> Ordering {
+  let by_name = || {
+    let (a, b) = (
+      a.file_name_lossy().to_lowercase(),
+      b.file_name_lossy().to_lowercase(),
+    );
+    if natural {
+      natural_cmp(&a, &b)
+    } else {
+      a.cmp(&b)
+    }
+  };
+
+  match key {
+    SortKey::Name => by_name(),
+    SortKey::Modified => a.modified().cmp(&b.modified()).then_with(by_name),
+    SortKey::Created => a.created().cmp(&b.created()).then_with(by_name),
+    SortKey::Size => a.size.cmp(&b.size).then_with(by_name),
+  }
+}
+
+// Compares runs of ASCII digits by their value, the rest char by char. Of
+// equal numbers the one with fewer leading zeros comes first.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+  let (mut a, mut b) = (a.chars().peekable(), b.chars().peekable());
+  loop {
+    let ord = match (a.peek(), b.peek()) {
+      (None, None) => return Ordering::Equal,
+      (None, Some(_)) => return Ordering::Less,
+      (Some(_), None) => return Ordering::Greater,
+      (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+        let (x, y) = (digit_run(&mut a), digit_run(&mut b));
+        let (x_value, y_value) =
+          (x.trim_start_matches('0'), y.trim_start_matches('0'));
+        x_value
+          .len()
+          .cmp(&y_value.len())
+          .then_with(|| x_value.cmp(y_value))
+          .then_with(|| x.len().cmp(&y.len()))
+      }
+      (Some(x), Some(y)) => {
+        let ord = x.cmp(y);
+        a.next();
+        b.next();
+        ord
+      }
+    };
+    if ord != Ordering::Equal {
+      return ord;
+    }
+  }
+}
+
+// Takes the leading digits off `chars`
+fn digit_run(chars: &mut Peekable<Chars<'_>>) -> String {
+  let mut run = String::new();
+  while let Some(c) = chars.next_if(char::is_ascii_digit) {
+    run.push(c);
+  }
+  run
+}
@@ -0,0 +1,239 @@
+use super::{canonicalize, Tree};
+use indextree::{NodeEdge, NodeId};
+use std::{
+  collections::{HashMap, HashSet},
+  time::{Duration, SystemTime},
+};
+
+/// Key of `Tree::extension_stats` for files without an extension.
+pub const NO_EXTENSION: &str = "<none>";
+
+/// Key of `Tree::code_language_stats` for fences without a language.
+pub const NO_LANGUAGE: &str = "<none>";
+
+/// Counts of a [Tree], the root directory is included in `dirs`.
+///
+/// `total_bytes` sums the size of files and stays 0 for nodes whose
+/// metadata could not be read.
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize)]
+pub struct TreeStats {
+  pub files: usize,
+  pub dirs: usize,
+  pub total_bytes: u64,
+}
+
+/// Profile of the walk building a [Tree], see `Tree::traversal_stats`.
+///
+/// `files` and `dirs` count what ended up in the tree, the root included.
+/// `bytes_read` sums the content loaded into the nodes and stays 0 unless
+/// content is read.
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize)]
+pub struct TraversalStats {
+  pub duration: Duration,
+  pub files: usize,
+  pub dirs: usize,
+  pub bytes_read: u64,
+  pub threads_used: usize,
+}
+
+impl Tree {
+  // Profile of the walk of `init` and the like, None for trees built
+  // otherwise, e.g. with `Tree::new`.
+  pub fn traversal_stats(&self) -> Option<TraversalStats> {
+    self.traversal_stats
+  }
+
+  // Counts the nodes and loaded content after a walk of `duration`
+  pub(crate) fn collect_traversal_stats(
+    &self,
+    duration: Duration,
+    threads_used: usize,
+  ) -> TraversalStats {
+    let TreeStats { files, dirs, .. } = self.stats();
+    let bytes_read = self
+      .iter()
+      .filter_map(|node| {
+        let text = node.file_text.as_ref()?;
+        // a preview is only read in part
+        Some(match node.content_hash {
+          Some(_) => node.size,
+          None => text.len() as u64,
+        })
+      })
+      .sum();
+
+    TraversalStats {
+      duration,
+      files,
+      dirs,
+      bytes_read,
+      threads_used,
+    }
+  }
+
+  // Counts files, directories and bytes in a single pass.
+  pub fn stats(&self) -> TreeStats {
+    self.iter().fold(TreeStats::default(), |mut stats, node| {
+      if node.is_dir() {
+        stats.dirs += 1;
+      } else {
+        stats.files += 1;
+        stats.total_bytes += node.size;
+      }
+      stats
+    })
+  }
+
+  // Files of 0 bytes, depth-first. With `whitespace_only` also notes read in
+  // full whose text is nothing but whitespace.
+  pub fn empty_files(&self, whitespace_only: bool) -> Vec<NodeId> {
+    self
+      .root
+      .descendants(&self.inner)
+      .filter(|id| {
+        let node = self.inner[*id].get();
+        if node.is_dir() {
+          return false;
+        }
+        // a preview doesn't tell about the rest
+        let blank = whitespace_only
+          && node.content_hash.is_some()
+          && node
+            .file_text
+            .as_deref()
+            .is_some_and(|t| t.trim().is_empty());
+        node.size == 0 || blank
+      })
+      .collect()
+  }
+
+  // Depth of the deepest node below the root, 0 for the root alone.
+  pub fn max_depth(&self) -> usize {
+    let base = self.inner[self.root].get().depth;
+    self
+      .iter()
+      .map(|node| node.depth.saturating_sub(base))
+      .max()
+      .unwrap_or(0)
+  }
+
+  // Maps each lowercased file extension to the count and summed size of its
+  // files, [NO_EXTENSION] for files without. Directories are left out.
+  pub fn extension_stats(&self) -> HashMap<String, (usize, u64)> {
+    let mut stats: HashMap<String, (usize, u64)> = HashMap::new();
+    for node in self.iter().filter(|node| !node.is_dir()) {
+      let extension = node.path().extension().map_or_else(
+        || NO_EXTENSION.to_string(),
+        |ext| ext.to_string_lossy().to_lowercase(),
+      );
+      let entry = stats.entry(extension).or_default();
+      entry.0 += 1;
+      entry.1 += node.size;
+    }
+    stats
+  }
+
+  // Counts the fenced code blocks of read notes per lowercased language,
+  // [NO_LANGUAGE] for unlabeled fences.
+  pub fn code_language_stats(&self) -> HashMap<String, usize> {
+    let mut stats: HashMap<String, usize> = HashMap::new();
+    for language in self.iter().flat_map(|node| &node.code_languages) {
+      let key = if language.is_empty() {
+        NO_LANGUAGE
+      } else {
+        language
+      };
+      *stats.entry(key.to_string()).or_default() += 1;
+    }
+    stats
+  }
+
+  // The `n` biggest files, biggest first and ties by path.
+  pub fn largest_files(&self, n: usize) -> Vec<NodeId> {
+    let mut files: Vec<NodeId> = self
+      .root
+      .descendants(&self.inner)
+      .filter(|id| !self.inner[*id].get().is_dir())
+      .collect();
+    files.sort_by(|a, b| {
+      let (a, b) = (self.inner[*a].get(), self.inner[*b].get());
+      b.size.cmp(&a.size).then_with(|| a.path().cmp(b.path()))
+    });
+    files.truncate(n);
+    files
+  }
+
+  // Files modified less than `within` ago, most recent first. Files
+  // without a modification time are left out.
+  pub fn recently_modified(&self, within: Duration) -> Vec<NodeId> {
+    let cutoff = SystemTime::now().checked_sub(within);
+    let mut recent: Vec<(SystemTime, NodeId)> = self
+      .root
+      .descendants(&self.inner)
+      .filter_map(|id| {
+        let node = self.inner[id].get();
+        let modified = node.modified().filter(|_| !node.is_dir())?;
+        cutoff
+          .map_or(true, |cutoff| modified > cutoff)
+          .then_some((modified, id))
+      })
+      .collect();
+
+    recent.sort_by(|a, b| {
+      b.0.cmp(&a.0).then_with(|| {
+        self.inner[a.1]
+          .get()
+          .path()
+          .cmp(self.inner[b.1].get().path())
+      })
+    });
+    recent.into_iter().map(|(_, id)| id).collect()
+  }
+
+  // Stores the summed size of everything beneath each directory into its
+  // `recursive_size`, files without metadata count as 0. A symlinked file
+  // pointing into the tree is only counted once, via its target.
+  pub fn compute_dir_sizes(&mut self) {
+    let linked: HashSet<NodeId> = self
+      .root
+      .descendants(&self.inner)
+      .filter(|id| {
+        let node = self.inner[*id].get();
+        !node.is_dir()
+          && node.is_symlink()
+          && canonicalize(node.path())
+            .map(|target| self.paths.contains_key(&target))
+            .unwrap_or(false)
+      })
+      .collect();
+
+    // post-order, children are summed up before their parent
+    let post_order: Vec<NodeId> = self
+      .root
+      .traverse(&self.inner)
+      .filter_map(|edge| match edge {
+        NodeEdge::End(id) => Some(id),
+        NodeEdge::Start(_) => None,
+      })
+      .collect();
+
+    for node_id in post_order {
+      if !self.inner[node_id].get().is_dir() {
+        continue;
+      }
+      let total: u64 = node_id
+        .children(&self.inner)
+        .filter(|id| !linked.contains(id))
+        .map(|id| {
+          let child = self.inner[id].get();
+          if child.is_dir() {
+            child.recursive_size
+          } else {
+            child.size
+          }
+        })
+        .sum();
+      self.inner[node_id].get_mut().recursive_size = total;
+    }
+  }
+}
@@ -1,33 +1,189 @@
-use super::Node;
+use super::{canonicalize, node::ContentReader, Node};
 use crossbeam::channel::Sender;
 use ignore::{
   DirEntry, Error as IgnoreError, ParallelVisitor, ParallelVisitorBuilder, WalkState,
 };
+use std::{
+  fs, io,
+  path::PathBuf,
+  sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc, Condvar, Mutex,
+  },
+};
 
 pub enum TraversalState {
   Ongoing(Node),
+  Failed(EntryError),
   Done,
 }
 
+/// Entry the walker couldn't read.
+#[derive(Debug)]
+pub struct EntryError {
+  // empty if unknown
+  pub path: PathBuf,
+  pub reason: String,
+  pub kind: Option<io::ErrorKind>,
+}
+
+/// Cap on the nodes sent by all visitors of a walk.
+#[derive(Debug)]
+pub struct NodeBudget {
+  max: usize,
+  sent: AtomicUsize,
+  exceeded: AtomicBool,
+}
+
+impl NodeBudget {
+  pub fn new(max: usize) -> Self {
+    Self {
+      max,
+      sent: AtomicUsize::new(0),
+      exceeded: AtomicBool::new(false),
+    }
+  }
+
+  // Claims a node, false once the cap is reached
+  fn take(&self) -> bool {
+    let taken = self.sent.fetch_add(1, Ordering::Relaxed) < self.max;
+    if !taken {
+      self.exceeded.store(true, Ordering::Relaxed);
+    }
+    taken
+  }
+
+  // If any node was turned away
+  pub fn exceeded(&self) -> bool {
+    self.exceeded.load(Ordering::Relaxed)
+  }
+}
+
+/// Counting semaphore on the files read by all visitors of a walk.
+#[derive(Debug)]
+pub struct ReadGate {
+  permits: Mutex<usize>,
+  released: Condvar,
+}
+
+// Hands its permit back on drop
+struct ReadPermit<'a>(&'a ReadGate);
+
+impl ReadGate {
+  pub fn new(permits: usize) -> Self {
+    Self {
+      permits: Mutex::new(permits.max(1)),
+      released: Condvar::new(),
+    }
+  }
+
+  // Blocks until a permit is free
+  fn acquire(&self) -> ReadPermit<'_> {
+    let mut permits = self.permits.lock().unwrap_or_else(|e| e.into_inner());
+    while *permits == 0 {
+      permits = self
+        .released
+        .wait(permits)
+        .unwrap_or_else(|e| e.into_inner());
+    }
+    *permits -= 1;
+    ReadPermit(self)
+  }
+}
+
+impl Drop for ReadPermit<'_> {
+  fn drop(&mut self) {
+    let mut permits = self.0.permits.lock().unwrap_or_else(|e| e.into_inner());
+    *permits += 1;
+    self.0.released.notify_one();
+  }
+}
+
 pub struct BranchVisitor {
   tx: Sender<TraversalState>,
-  ctn: bool,
+  reader: ContentReader,
+  budget: Option<Arc<NodeBudget>>,
+  cancel: Option<Arc<AtomicBool>>,
+  gate: Option<Arc<ReadGate>>,
+  max_symlink_depth: usize,
+  tree_root: Option<Arc<PathBuf>>,
 }
 
 pub struct BranchVisitorBuilder {
   tx: Sender<TraversalState>,
-  ctn: bool,
+  reader: ContentReader,
+  budget: Option<Arc<NodeBudget>>,
+  cancel: Option<Arc<AtomicBool>>,
+  gate: Option<Arc<ReadGate>>,
+  max_symlink_depth: usize,
+  tree_root: Option<Arc<PathBuf>>,
 }
 
 impl BranchVisitorBuilder {
-  pub fn new(tx: Sender<TraversalState>, ctn: bool) -> Self {
-    Self { tx, ctn }
+  pub fn new(tx: Sender<TraversalState>, reader: ContentReader) -> Self {
+    Self {
+      tx,
+      reader,
+      budget: None,
+      cancel: None,
+      gate: None,
+      max_symlink_depth: usize::MAX,
+      tree_root: None,
+    }
+  }
+
+  // Shares `budget` with all visitors
+  pub fn with_budget(mut self, budget: Arc<NodeBudget>) -> Self {
+    self.budget = Some(budget);
+    self
+  }
+
+  // Visitors quit once `cancel` is set, the root is visited regardless
+  pub fn with_cancel(mut self, cancel: Arc<AtomicBool>) -> Self {
+    self.cancel = Some(cancel);
+    self
+  }
+
+  // Content reads of all visitors wait for a permit of `gate`, the walk
+  // itself doesn't
+  pub fn with_read_gate(mut self, gate: Arc<ReadGate>) -> Self {
+    self.gate = Some(gate);
+    self
+  }
+
+  // Followed links to directories are listed but not entered once more
+  // than `max` links were crossed to reach them
+  pub fn with_max_symlink_depth(mut self, max: usize) -> Self {
+    self.max_symlink_depth = max;
+    self
+  }
+
+  // Followed links to directories within the canonical `root` are dropped
+  // and not entered, the directory itself is reached on its own
+  pub fn with_tree_root(mut self, root: PathBuf) -> Self {
+    self.tree_root = Some(Arc::new(root));
+    self
   }
 }
 
 impl BranchVisitor {
-  pub fn new(tx: Sender<TraversalState>, ctn: bool) -> Self {
-    Self { tx, ctn }
+  // If `entry` is a followed link to a directory of the tree
+  fn links_within(&self, entry: &DirEntry) -> bool {
+    let Some(root) = &self.tree_root else {
+      return false;
+    };
+    entry.depth() > 0
+      && entry.path_is_symlink()
+      && entry.file_type().is_some_and(|ft| ft.is_dir())
+      && canonicalize(entry.path()).is_ok_and(|target| target.starts_with(&**root))
+  }
+
+  // Stops the walk once the collector is gone
+  fn send(&self, state: TraversalState, then: WalkState) -> WalkState {
+    match self.tx.send(state) {
+      Ok(_) => then,
+      Err(_) => WalkState::Quit,
+    }
   }
 }
 
@@ -39,17 +195,89 @@ impl From<Node> for TraversalState {
 
 impl ParallelVisitor for BranchVisitor {
   fn visit(&mut self, entry: Result<DirEntry, IgnoreError>) -> WalkState {
-    entry
-      .map(|e| TraversalState::from(Node::from((&e, self.ctn))))
-      .map(|n| self.tx.send(n).unwrap())
-      .map(|_| WalkState::Continue)
-      .unwrap_or_else(|_| WalkState::Skip)
+    match entry {
+      Ok(e) => {
+        let cancelled = self
+          .cancel
+          .as_ref()
+          .is_some_and(|cancel| cancel.load(Ordering::Relaxed));
+        if cancelled && e.depth() > 0 {
+          return WalkState::Quit;
+        }
+        // the same directory either way, whichever thread comes first
+        if self.links_within(&e) {
+          return WalkState::Skip;
+        }
+        if let Some(budget) = &self.budget {
+          if !budget.take() {
+            return WalkState::Quit;
+          }
+        }
+        let reads = !self.reader.mode().is_none()
+          && e.file_type().is_some_and(|ft| ft.is_file());
+        let permit = match &self.gate {
+          Some(gate) if reads => Some(gate.acquire()),
+          _ => None,
+        };
+        let node = Node::from((&e, &self.reader));
+        drop(permit);
+        let too_deep = e.path_is_symlink()
+          && e.file_type().is_some_and(|ft| ft.is_dir())
+          && symlinks_along(&e) > self.max_symlink_depth;
+        let then = if too_deep {
+          WalkState::Skip
+        } else {
+          WalkState::Continue
+        };
+        self.send(TraversalState::from(node), then)
+      }
+      Err(e) => {
+        let kind = e.io_error().map(|io_err| io_err.kind());
+        let (path, reason) = split_error(e);
+        let error = EntryError { path, reason, kind };
+        self.send(TraversalState::Failed(error), WalkState::Skip)
+      }
+    }
+  }
+}
+
+// Links crossed from the root down to `entry`, itself included
+fn symlinks_along(entry: &DirEntry) -> usize {
+  let parents = entry
+    .path()
+    .ancestors()
+    .skip(1)
+    .take(entry.depth().saturating_sub(1))
+    .filter(|dir| {
+      fs::symlink_metadata(dir).is_ok_and(|m| m.file_type().is_symlink())
+    })
+    .count();
+  parents + usize::from(entry.path_is_symlink())
+}
+
+// Path of the failed entry, empty if unknown, and the bare reason
+fn split_error(err: IgnoreError) -> (PathBuf, String) {
+  match err {
+    IgnoreError::WithPath { path, err } => (path, err.to_string()),
+    IgnoreError::WithDepth { err, .. } | IgnoreError::WithLineNumber { err, .. } => {
+      split_error(*err)
+    }
+    IgnoreError::Loop { ref child, .. } => (child.clone(), err.to_string()),
+    _ => (PathBuf::new(), err.to_string()),
   }
 }
 
 impl<'s> ParallelVisitorBuilder<'s> for BranchVisitorBuilder {
   fn build(&mut self) -> Box<dyn ParallelVisitor + 's> {
-    let visitor = BranchVisitor::new(self.tx.clone(), self.ctn);
+    let visitor = BranchVisitor {
+      tx: self.tx.clone(),
+      reader: self.reader.clone(),
+      budget: self.budget.clone(),
+      cancel: self.cancel.clone(),
+      gate: self.gate.clone(),
+      max_symlink_depth: self.max_symlink_depth,
+      tree_root: self.tree_root.clone(),
+    };
     Box::new(visitor)
   }
 }
@@ -0,0 +1,404 @@
+use super::{
+  canonicalize,
+  node::{ContentReader, Node},
+  options::{EntryFilter, ReadMode, WalkOptions},
+  Tree, TreeError, TreeResult,
+};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use notify::{
+  event::{EventKind, ModifyKind, RenameMode},
+  Config, Event as RawEvent, RecommendedWatcher, RecursiveMode, Watcher,
+};
+use serde::Serialize;
+use std::{
+  collections::HashMap,
+  path::{Path, PathBuf},
+  sync::{
+    mpsc::{channel, Receiver, RecvTimeoutError},
+    Arc, Mutex,
+  },
+  thread,
+  time::{Duration, Instant},
+};
+
+/// Name of the event `Tree::watch_emit` sends to the frontend.
+pub const TREE_CHANGE_EVENT: &str = "tree://change";
+
+/// Incremental change of the watched directory.
+#[derive(Debug, Clone)]
+pub enum TreeEvent {
+  Created(Node),
+  Removed(PathBuf),
+  Modified(PathBuf),
+}
+
+/// Payload of [TREE_CHANGE_EVENT], a [TreeEvent] in short.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TreeChange {
+  pub kind: ChangeKind,
+  pub path: PathBuf,
+}
+
+impl From<&TreeEvent> for TreeChange {
+  fn from(event: &TreeEvent) -> Self {
+    let (kind, path) = match event {
+      TreeEvent::Created(node) => (ChangeKind::Created, node.path()),
+      TreeEvent::Removed(path) => (ChangeKind::Removed, path.as_path()),
+      TreeEvent::Modified(path) => (ChangeKind::Modified, path.as_path()),
+    };
+    Self {
+      kind,
+      path: path.to_owned(),
+    }
+  }
+}
+
+/// Keeps the watcher alive, watching stops on drop.
+pub struct WatchHandle {
+  // taken out early by `Tree::watch_emit` once its window is gone
+  watcher: Arc<Mutex<Option<RecommendedWatcher>>>,
+}
+
+/// What happened to the path of a [TreeChange].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+  Created,
+  Removed,
+  Modified,
+}
+
+impl Tree {
+  // Watches `dir` recursively and calls back with debounced [TreeEvent]s.
+  //
+  // Meant to be started right after `init` with the same read mode and
+  // options, entries excluded by the traversal are excluded here too and
+  // created nodes are read alike. Ignore files in and below `dir` are
+  // respected, those above it aren't.
+  pub fn watch<F>(
+    dir: &str,
+    read_ctn: impl Into<ReadMode>,
+    opts: &WalkOptions,
+    callback: F,
+  ) -> TreeResult<WatchHandle>
+  where
+    F: Fn(TreeEvent) + Send + 'static,
+  {
+    let root =
+      canonicalize(dir).map_err(|e| TreeError::from_io(e, Path::new(dir)))?;
+    let reader = ContentReader::new(read_ctn.into(), opts);
+    let mut filter = WatchFilter::new(&root, reader, opts)?;
+
+    let (tx, rx) = channel::<notify::Result<RawEvent>>();
+    let mut watcher =
+      RecommendedWatcher::new(tx, Config::default()).map_err(TreeError::Watch)?;
+    watcher
+      .watch(&root, RecursiveMode::Recursive)
+      .map_err(TreeError::Watch)?;
+
+    let mut debouncer = Debouncer::new(opts.watch_debounce);
+    thread::spawn(move || {
+      loop {
+        let received = match debouncer.remaining() {
+          Some(wait) => rx.recv_timeout(wait),
+          None => rx_recv(&rx),
+        };
+
+        match received {
+          Ok(Ok(RawEvent { paths, kind, .. })) => {
+            for (path, change) in raw_changes(kind, paths) {
+              if filter.keep(&path, change) {
+                debouncer.push(path, change);
+              }
+            }
+          }
+          Ok(Err(_e)) => continue,
+          Err(RecvTimeoutError::Timeout) => {
+            for (path, change) in debouncer.drain() {
+              if let Some(event) = filter.to_event(path, change) {
+                callback(event);
+              }
+            }
+          }
+          // the watcher was dropped
+          Err(RecvTimeoutError::Disconnected) => break,
+        }
+      }
+    });
+
+    Ok(WatchHandle {
+      watcher: Arc::new(Mutex::new(Some(watcher))),
+    })
+  }
+
+  // Same as `watch`, sending each event as a [TreeChange] to all windows
+  // under [TREE_CHANGE_EVENT]. Watching stops when the handle is dropped or
+  // `window` is destroyed, whichever comes first.
+  pub fn watch_emit<R: tauri::Runtime>(
+    window: &tauri::Window<R>,
+    dir: &str,
+    read_ctn: impl Into<ReadMode>,
+    opts: &WalkOptions,
+  ) -> TreeResult<WatchHandle> {
+    use tauri::Manager;
+
+    let app = window.app_handle();
+    let handle = Self::watch(dir, read_ctn, opts, move |event| {
+      // a window going away meanwhile isn't an error of the watcher
+      let _ = app.emit_all(TREE_CHANGE_EVENT, TreeChange::from(&event));
+    })?;
+
+    // weak, the handle alone decides how long the watcher lives otherwise
+    let watcher = Arc::downgrade(&handle.watcher);
+    window.on_window_event(move |event| {
+      if let tauri::WindowEvent::Destroyed = event {
+        if let Some(watcher) = watcher.upgrade() {
+          // dropping the watcher ends its thread
+          watcher.lock().unwrap_or_else(|e| e.into_inner()).take();
+        }
+      }
+    });
+    Ok(handle)
+  }
+}
+
+/// Collapses bursts of changes into one net change per path, released
+/// once no change came in for the window.
+#[derive(Debug)]
+pub struct Debouncer {
+  window: Duration,
+  // first and last change of each path, in order of the first one
+  pending: Vec<(PathBuf, ChangeKind, ChangeKind)>,
+  // position of each path in `pending`
+  positions: HashMap<PathBuf, usize>,
+  last_change: Instant,
+}
+
+impl Debouncer {
+  pub fn new(window: Duration) -> Self {
+    Self {
+      window,
+      pending: Vec::new(),
+      positions: HashMap::new(),
+      last_change: Instant::now(),
+    }
+  }
+
+  pub fn push(&mut self, path: PathBuf, change: ChangeKind) {
+    match self.positions.get(&path) {
+      Some(&idx) => self.pending[idx].2 = change,
+      None => {
+        self.positions.insert(path.clone(), self.pending.len());
+        self.pending.push((path, change, change));
+      }
+    }
+    self.last_change = Instant::now();
+  }
+
+  // Time left until the pending changes are due, None if there are none
+  pub fn remaining(&self) -> Option<Duration> {
+    (!self.pending.is_empty())
+      .then(|| self.window.saturating_sub(self.last_change.elapsed()))
+  }
+
+  // Takes the net change of each path, a path created and removed again
+  // within the burst is dropped. Doesn't wait for the window.
+  pub fn drain(&mut self) -> Vec<(PathBuf, ChangeKind)> {
+    self.positions.clear();
+    self
+      .pending
+      .drain(..)
+      .filter_map(|(path, first, last)| {
+        let change = match (first, last) {
+          (ChangeKind::Created, ChangeKind::Removed) => return None,
+          // a new file stays created while being written
+          (ChangeKind::Created, _) => ChangeKind::Created,
+          (_, ChangeKind::Removed) => ChangeKind::Removed,
+          // replaced, e.g. by an editor saving through a rename
+          _ => ChangeKind::Modified,
+        };
+        Some((path, change))
+      })
+      .collect()
+  }
+}
+
+// Changes of the paths of a raw event, none for access and the like
+fn raw_changes(kind: EventKind, paths: Vec<PathBuf>) -> Vec<(PathBuf, ChangeKind)> {
+  let change = match kind {
+    EventKind::Create(_) => ChangeKind::Created,
+    // the old path of a rename is gone, the new one appears
+    EventKind::Modify(ModifyKind::Name(RenameMode::From)) => ChangeKind::Removed,
+    EventKind::Modify(ModifyKind::Name(RenameMode::To)) => ChangeKind::Created,
+    EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+      return paths
+        .into_iter()
+        .enumerate()
+        .map(|(idx, path)| {
+          let change = if idx == 0 {
+            ChangeKind::Removed
+          } else {
+            ChangeKind::Created
+          };
+          (path, change)
+        })
+        .collect();
+    }
+    EventKind::Modify(_) => ChangeKind::Modified,
+    EventKind::Remove(_) => ChangeKind::Removed,
+    _ => return Vec::new(),
+  };
+  paths.into_iter().map(|path| (path, change)).collect()
+}
+
+// Blocks for the next event, uniform with `recv_timeout`
+fn rx_recv<T>(rx: &Receiver<T>) -> Result<T, RecvTimeoutError> {
+  rx.recv().map_err(|_| RecvTimeoutError::Disconnected)
+}
+
+// Applies the traversal options to raw watcher paths
+struct WatchFilter {
+  root: PathBuf,
+  show_hidden: bool,
+  entries: Option<EntryFilter>,
+  // names of the ignore files read in each directory, by rising precedence
+  ignore_files: Vec<String>,
+  // ignore files of each directory seen, loaded on first use
+  dirs: HashMap<PathBuf, Gitignore>,
+  // the global git excludes, empty if not respected
+  global: Gitignore,
+  // reads created nodes as the traversal would
+  reader: ContentReader,
+}
+
+impl WatchFilter {
+  fn new(
+    root: &Path,
+    reader: ContentReader,
+    opts: &WalkOptions,
+  ) -> TreeResult<Self> {
+    let entries = EntryFilter::new(root, opts)
+      .map_err(|e| TreeError::InvalidGlob(e.to_string()))?;
+
+    let mut ignore_files = Vec::new();
+    if opts.git_ignore {
+      ignore_files.push(".gitignore".to_string());
+    }
+    if opts.ignore {
+      ignore_files.push(".ignore".to_string());
+    }
+    ignore_files.extend(opts.custom_ignore_files.iter().cloned());
+
+    // same as the walker, which needs a repository unless `git_ignore` is on
+    let in_repo = || root.ancestors().any(|dir| dir.join(".git").exists());
+    let global = if opts.git_global && (opts.git_ignore || in_repo()) {
+      Gitignore::global().0
+    } else {
+      Gitignore::empty()
+    };
+
+    Ok(Self {
+      root: root.to_owned(),
+      show_hidden: opts.show_hidden,
+      entries,
+      ignore_files,
+      dirs: HashMap::new(),
+      global,
+      reader,
+    })
+  }
+
+  fn keep(&mut self, path: &Path, change: ChangeKind) -> bool {
+    // an edited ignore file is read again
+    let is_ignore_file = path
+      .file_name()
+      .and_then(|name| name.to_str())
+      .is_some_and(|name| self.ignore_files.iter().any(|f| f == name));
+    if let Some(dir) = path.parent().filter(|_| is_ignore_file) {
+      self.dirs.remove(dir);
+    }
+
+    let relative = match path.strip_prefix(&self.root) {
+      Ok(rel) if rel.as_os_str().is_empty() => return false,
+      Ok(rel) => rel,
+      Err(_) => return false,
+    };
+
+    let hidden = relative
+      .components()
+      .any(|c| c.as_os_str().to_string_lossy().starts_with('.'));
+    if hidden && !self.show_hidden {
+      return false;
+    }
+
+    // a removed path can't be told a directory any more, the filters of
+    // files would turn a removed directory away
+    let removed = change == ChangeKind::Removed;
+    let is_dir = !removed && path.is_dir();
+    if let Some(entries) = self.entries.as_ref().filter(|_| !removed) {
+      if !entries.keep(path, is_dir) {
+        return false;
+      }
+    }
+
+    !self.ignored(path, is_dir)
+  }
+
+  // If `path` or a directory between it and the root is ignored
+  fn ignored(&mut self, path: &Path, is_dir: bool) -> bool {
+    let relative = match path.strip_prefix(&self.root) {
+      Ok(rel) => rel.to_owned(),
+      Err(_) => return false,
+    };
+    let count = relative.components().count();
+    let mut current = self.root.clone();
+    for (idx, component) in relative.components().enumerate() {
+      current.push(component);
+      if self.matched(&current, idx + 1 < count || is_dir) {
+        return true;
+      }
+    }
+    false
+  }
+
+  // The deepest ignore file with a say about `path` wins, as in the walk
+  fn matched(&mut self, path: &Path, is_dir: bool) -> bool {
+    for dir in path.ancestors().skip(1) {
+      if !dir.starts_with(&self.root) {
+        break;
+      }
+      let matched = self.dir_ignores(dir).matched(path, is_dir);
+      if !matched.is_none() {
+        return matched.is_ignore();
+      }
+    }
+    self.global.matched(path, is_dir).is_ignore()
+  }
+
+  fn dir_ignores(&mut self, dir: &Path) -> &Gitignore {
+    if !self.dirs.contains_key(dir) {
+      let mut builder = GitignoreBuilder::new(dir);
+      // missing ignore files are fine, later ones take precedence
+      for file_name in &self.ignore_files {
+        builder.add(dir.join(file_name));
+      }
+      // broken lines are skipped like in the walk
+      let ignores = builder.build().unwrap_or_else(|_| Gitignore::empty());
+      self.dirs.insert(dir.to_owned(), ignores);
+    }
+    &self.dirs[dir]
+  }
+
+  fn to_event(&self, path: PathBuf, change: ChangeKind) -> Option<TreeEvent> {
+    match change {
+      ChangeKind::Created => {
+        let depth = path.strip_prefix(&self.root).ok()?.components().count();
+        // gone again before the window closed
+        let node = Node::from_path(&path, depth, &self.reader).ok()?;
+        Some(TreeEvent::Created(node))
+      }
+      ChangeKind::Removed => Some(TreeEvent::Removed(path)),
+      ChangeKind::Modified => Some(TreeEvent::Modified(path)),
+    }
+  }
+}